@@ -1,20 +1,43 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use colored::control::set_override;
 use core::panic;
 use iced_x86::{FormatterOutput, FormatterTextKind};
 use rayon::prelude::*;
 use regex::Regex;
 use ropr::{
-	binary::Binary, disassembler::Disassembly, formatter::ColourFormatter, gadgets::Gadget,
+	binary::{Binary, Bitness, Section},
+	chain::{Chain, Goal},
+	diff::{Change, DiffRecord, GadgetDiff},
+	error,
+	finder::GadgetFinder,
+	formatter::ColourFormatter,
+	gadgets::Gadget,
+	mitigations::Mitigations,
+	output::{locate, symbol_name, write_python, GadgetRecord},
+	patterns::{find_patterns, Pattern, PatternRecord},
+	process::Process,
+	query::GadgetFilter,
+	srop::{FrameRecord, SigreturnFrame},
 };
+use serde::Serialize;
 use std::{
-	collections::HashMap,
+	env,
 	error::Error,
+	fs::{read_dir, File},
 	io::{stdout, BufWriter, Write},
-	path::PathBuf,
+	num::ParseIntError,
+	path::{Path, PathBuf},
 	time::Instant,
 };
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+	Text,
+	Json,
+	Jsonl,
+	Python,
+}
+
 #[derive(Parser)]
 #[clap(version)]
 struct Opt {
@@ -46,6 +69,27 @@ struct Opt {
 	#[clap(short = 'b', long)]
 	base_pivot: bool,
 
+	/// Filters for RISC-V and MIPS gadgets which restore the return address and stack pointer from
+	/// the stack before returning
+	#[clap(long)]
+	epilogue: bool,
+
+	/// Shows the function containing each gadget eg. `<memcpy+0x1a>`, resolved from ELF symbol tables or PE exports
+	#[clap(long)]
+	symbols: bool,
+
+	/// Reports the exploit mitigations the binary was built with, such as PIE, NX, RELRO, stack canaries and CET, before its gadgets
+	#[clap(long)]
+	mitigations: bool,
+
+	/// Filters for gadgets which load the number of `sigreturn` into `eax` and make the syscall eg. `mov eax, 0xf; syscall`
+	#[clap(long)]
+	sigreturn: bool,
+
+	/// Shows a summary of the registers, memory and stack space used by each gadget
+	#[clap(short = 'e', long)]
+	effects: bool,
+
 	/// Maximum number of instructions in a gadget
 	#[clap(short, long, default_value = "6")]
 	max_instr: u8,
@@ -54,58 +98,540 @@ struct Opt {
 	#[clap(short = 'R', long)]
 	regex: Vec<String>,
 
+	/// Filters gadgets with a query over their effects eg. `writes(rdi) and not writes(rsp) and stack_delta <= 24 and tail == ret and no_mem_write`
+	#[clap(short = 'q', long)]
+	query: Vec<String>,
+
+	/// Only reports jump and call gadgets starting with an `endbr` instruction, which are the only targets of indirect branches under CET Indirect Branch Tracking (`true` or `false`) - enabled by default for binaries which opt into IBT
+	#[clap(long)]
+	ibt: Option<bool>,
+
 	/// Treats the input file as a blob of code (`true` or `false`)
 	#[clap(long)]
 	raw: Option<bool>,
 
+	/// Selects a single architecture from a universal Mach-O binary eg. `x86_64` or `arm64` - all supported architectures are searched by default
+	#[clap(long)]
+	slice: Option<String>,
+
+	/// Finds the executable sections of ELF binaries from their section headers, so that gadgets are labelled with section names such as `.plt` or `.text`
+	#[clap(long)]
+	section_headers: bool,
+
+	/// Only searches sections with the given name eg. `.text` for `--section-headers`, `LOAD2`, or the path of a library within a process or core dump
+	#[clap(long)]
+	section: Vec<String>,
+
+	/// Only searches within the function with the given name
+	#[clap(long)]
+	function: Vec<String>,
+
+	/// Excludes gadgets within the function with the given name
+	#[clap(long)]
+	exclude_function: Vec<String>,
+
+	/// Relocates the binary so that it is loaded at the given address (in hexadecimal) eg. a leaked library base `0x7ffff7dd5000`
+	#[clap(long, value_parser = parse_address)]
+	base: Option<usize>,
+
 	/// Search between address ranges (in hexadecial) eg. `0x1234-0x4567`
 	#[clap(long)]
 	range: Vec<String>,
 
+	/// Excludes gadgets whose address contains any of the given bytes (in hexadecimal) eg. `000a0d`
+	#[clap(long)]
+	bad_bytes: Option<String>,
+
+	/// Also excludes gadgets whose instruction encoding contains any of the bytes given by `--bad-bytes`
+	#[clap(long)]
+	bad_bytes_encoding: bool,
+
+	/// Builds a ROP chain for the given goal from the found gadgets eg. `rdi=0x1234, rax=59; syscall`, `execve(0x1234)` or `mprotect(0x1000, 0x2000)`
+	#[clap(long)]
+	chain: Option<String>,
+
+	/// Builds a sigreturn frame which sets the given registers eg. `rip=0x401000, rsp=0x7ffe0000, rax=59, rdi=0x1234`, along with a gadget which invokes sigreturn - i386 frames are for `sigreturn` rather than `rt_sigreturn`
+	#[clap(long)]
+	srop: Option<String>,
+
+	/// Finds well-known patterns which span several gadgets - the ret2csu gadgets of `__libc_csu_init`, the PLT stub which calls `_dl_runtime_resolve` for ret2dlresolve, `_start` and the tail of `__libc_start_main` which `main` returns into - along with the registers they set
+	#[clap(long)]
+	patterns: bool,
+
+	/// Compares the gadgets found in the binary with those found in another build of it, listing those which were added, removed or relocated
+	#[clap(long)]
+	diff: Option<PathBuf>,
+
+	/// Output format - `json` emits a single array, `jsonl` emits one object per line, `python` emits a pwntools module naming each gadget
+	#[clap(short = 'f', long, value_enum, default_value = "text")]
+	format: Format,
+
+	/// Prints gadgets as they are found while disassembling a window of each section at a time, which bounds memory use for large binaries - gadgets are only sorted by address within each window, and `json` output is not supported
+	#[clap(long)]
+	stream: bool,
+
+	/// Keeps an index of the gadgets in each binary searched under `$XDG_CACHE_HOME/ropr` so that later searches of the same binary skip disassembling it
+	#[clap(long)]
+	cache: bool,
+
+	/// Keeps the index used by `--cache` in the given directory instead
+	#[clap(long)]
+	cache_dir: Option<PathBuf>,
+
 	/// Show duplicated gadgets
 	#[clap(short = 'u', long)]
 	nouniq: bool,
 
-	/// The path of the file to inspect
-	binary: PathBuf,
+	/// Searches the executable memory of a running process on Linux instead of a file, reporting gadgets at their addresses in the process
+	#[clap(long, conflicts_with = "binary")]
+	pid: Option<u32>,
+
+	/// The paths of the files to inspect - directories are searched recursively, skipping files which aren't executables
+	#[clap(required_unless_present = "pid")]
+	binary: Vec<PathBuf>,
+}
+
+fn parse_address(s: &str) -> Result<usize, ParseIntError> {
+	usize::from_str_radix(s.trim_start_matches("0x"), 16)
+}
+
+fn default_cache_dir() -> Result<PathBuf, Box<dyn Error>> {
+	let cache = env::var_os("XDG_CACHE_HOME")
+		.map(PathBuf::from)
+		.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+		.ok_or("unable to find a cache directory - use `--cache-dir` instead")?;
+	Ok(cache.join("ropr"))
+}
+
+fn parse_bytes(s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+	let digits = s
+		.replace("\\x", "")
+		.replace("0x", "")
+		.replace([',', ' '], "");
+	if !digits.len().is_multiple_of(2) {
+		return Err(format!("odd number of hexadecimal digits in `{}`", s).into());
+	}
+	(0..digits.len())
+		.step_by(2)
+		.map(|i| Ok(u8::from_str_radix(&digits[i..i + 2], 16)?))
+		.collect()
+}
+
+/// What is shown alongside each gadget in text output
+#[derive(Clone, Copy)]
+struct Annotations {
+	effects: bool,
+	symbols: bool,
+	sections: bool,
+	shadow_stack: bool,
 }
 
-fn write_gadgets(mut w: impl Write, gadgets: &[(Gadget, usize)]) {
+/// Returns the number of gadgets written
+fn write_gadgets(
+	mut w: impl Write,
+	gadgets: impl IntoIterator<Item = (Gadget, usize)>,
+	sections: &[Section],
+	annotations: Annotations,
+) -> usize {
 	let mut output = ColourFormatter::new();
+	let mut count = 0;
 	for (gadget, address) in gadgets {
 		output.clear();
-		output.write(&format!("{:#010x}: ", address), FormatterTextKind::Function);
-		gadget.format_instruction(&mut output);
+		format_gadget(&mut output, &gadget, address, sections, annotations);
 		match writeln!(w, "{}", output) {
-			Ok(_) => (),
-			Err(_) => return, // Pipe closed - finished writing gadgets
+			Ok(_) => count += 1,
+			Err(_) => break, // Pipe closed - finished writing gadgets
+		}
+	}
+	count
+}
+
+/// Formats a gadget as a line of text, optionally along with the section and function containing
+/// it, whether it violates a shadow stack, and a summary of its effects
+fn format_gadget(
+	output: &mut ColourFormatter,
+	gadget: &Gadget,
+	address: usize,
+	sections: &[Section],
+	annotations: Annotations,
+) {
+	output.write(&format!("{:#010x}", address), FormatterTextKind::Function);
+	let section = match annotations.sections || annotations.symbols {
+		true => locate(gadget, address, sections).map(|(section, _)| section),
+		false => None,
+	};
+	let section_name = section.filter(|_| annotations.sections).map(Section::name);
+	let symbol = section
+		.filter(|_| annotations.symbols)
+		.and_then(|section| symbol_name(section, address));
+	let label = match (section_name, symbol) {
+		(Some(section), Some(symbol)) => Some(format!("{}:{}", section, symbol)),
+		(Some(section), None) => Some(section.to_string()),
+		(None, symbol) => symbol,
+	};
+	if let Some(label) = label {
+		output.write(&format!(" <{}>", label), FormatterTextKind::Text);
+	}
+	output.write(": ", FormatterTextKind::Function);
+	gadget.format_instruction(output);
+	if annotations.shadow_stack && gadget.violates_shadow_stack() {
+		output.write("  # violates shadow stack", FormatterTextKind::Text);
+	}
+	if annotations.effects {
+		output.write(
+			&format!("  # {}", gadget.effects()),
+			FormatterTextKind::Text,
+		);
+	}
+}
+
+/// The mitigations of a binary alongside its gadgets
+#[derive(Serialize)]
+struct Report<'r> {
+	mitigations: &'r Mitigations,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	gadgets: Option<&'r [GadgetRecord]>,
+}
+
+/// Writes a single array of gadgets, or an object with `mitigations` and `gadgets` fields if
+/// mitigations are given
+fn write_gadgets_json(
+	mut w: impl Write,
+	gadgets: impl IntoIterator<Item = (Gadget, usize)>,
+	sections: &[Section],
+	annotations: Annotations,
+	mitigations: Option<&Mitigations>,
+) -> usize {
+	let shadow_stack = annotations.shadow_stack;
+	let records = gadgets
+		.into_iter()
+		.map(|(gadget, address)| GadgetRecord::new(&gadget, address, sections, shadow_stack))
+		.collect::<Vec<_>>();
+	let written = match mitigations {
+		Some(mitigations) => {
+			let report = Report {
+				mitigations,
+				gadgets: Some(&records),
+			};
+			serde_json::to_writer_pretty(&mut w, &report)
+		}
+		None => serde_json::to_writer_pretty(&mut w, &records),
+	};
+	if written.is_ok() {
+		let _ = writeln!(w);
+	}
+	records.len()
+}
+
+/// Writes one gadget per line, preceded by a line with a `mitigations` object if mitigations
+/// are given
+fn write_gadgets_jsonl(
+	mut w: impl Write,
+	gadgets: impl IntoIterator<Item = (Gadget, usize)>,
+	sections: &[Section],
+	annotations: Annotations,
+	mitigations: Option<&Mitigations>,
+) -> usize {
+	if let Some(mitigations) = mitigations {
+		let header = Report {
+			mitigations,
+			gadgets: None,
+		};
+		if serde_json::to_writer(&mut w, &header).is_err() || writeln!(w).is_err() {
+			return 0;
+		}
+	}
+	let mut count = 0;
+	for (gadget, address) in gadgets {
+		let record = GadgetRecord::new(&gadget, address, sections, annotations.shadow_stack);
+		if serde_json::to_writer(&mut w, &record).is_err() || writeln!(w).is_err() {
+			break; // Pipe closed - finished writing gadgets
+		}
+		count += 1;
+	}
+	count
+}
+
+fn write_diff(mut w: impl Write, diff: &GadgetDiff) {
+	let mut output = ColourFormatter::new();
+	for entry in diff.entries() {
+		output.clear();
+		let (sign, addresses) = match (entry.change, entry.old_address, entry.new_address) {
+			(Change::Relocated, Some(old), Some(new)) => {
+				("~", format!("{:#010x} -> {:#010x}", old, new))
+			}
+			(_, Some(old), None) => ("-", format!("{:#010x}", old)),
+			(_, _, Some(new)) => ("+", format!("{:#010x}", new)),
+			(_, None, None) => continue,
+		};
+		output.write(&format!("{} ", sign), FormatterTextKind::Text);
+		output.write(&format!("{}: ", addresses), FormatterTextKind::Function);
+		entry.gadget.format_instruction(&mut output);
+		if writeln!(w, "{}", output).is_err() {
+			return; // Pipe closed - finished writing gadgets
+		}
+	}
+}
+
+/// Writes each pattern as a heading followed by its gadgets, the registers it sets and how it is
+/// used
+fn write_patterns(
+	mut w: impl Write,
+	patterns: &[Pattern],
+	sections: &[Section],
+	annotations: Annotations,
+) {
+	let mut output = ColourFormatter::new();
+	for (index, pattern) in patterns.iter().enumerate() {
+		output.clear();
+		if index > 0 {
+			output.write("\n", FormatterTextKind::Text);
+		}
+		output.write(&pattern.kind().to_string(), FormatterTextKind::Text);
+		for step in pattern.steps() {
+			output.write(&format!("\n  {:<7}", step.role), FormatterTextKind::Text);
+			format_gadget(&mut output, &step.gadget, step.address, sections, annotations);
+		}
+		if !pattern.registers().is_empty() {
+			let registers = pattern
+				.registers()
+				.iter()
+				.map(|register| format!("{} = {}", register.register, register.source))
+				.collect::<Vec<_>>();
+			output.write(
+				&format!("\n  {}", registers.join(", ")),
+				FormatterTextKind::Text,
+			);
+		}
+		output.write(&format!("\n  {}", pattern.note()), FormatterTextKind::Text);
+		if writeln!(w, "{}", output).is_err() {
+			return; // Pipe closed - finished writing patterns
 		}
 	}
 }
 
+/// A gadget record tagged with the file it was found in
+#[derive(Serialize)]
+struct FileGadgetRecord {
+	file: PathBuf,
+	#[serde(flatten)]
+	record: GadgetRecord,
+}
+
+/// Adds the path of every file within `path` to `files`, without following symbolic links
+/// within directories
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+	if !path.is_dir() {
+		files.push(path.to_path_buf());
+		return;
+	}
+	let entries = match read_dir(path) {
+		Ok(entries) => entries,
+		Err(e) => {
+			eprintln!("warning: skipping {}: {}", path.display(), e);
+			return;
+		}
+	};
+	for entry in entries.flatten() {
+		match entry.file_type() {
+			Ok(t) if t.is_dir() => collect_files(&entry.path(), files),
+			Ok(t) if t.is_file() => files.push(entry.path()),
+			_ => (),
+		}
+	}
+}
+
+/// Whether the binary opts into CET shadow stacks through its GNU property note
+fn shadow_stack_enabled(binary: &Binary) -> bool {
+	binary.mitigations().is_ok_and(|mitigations| mitigations.shstk == Some(true))
+}
+
+/// Notes that only `endbr` gadgets are reported from `path` since it opts into IBT
+fn print_ibt_note(path: &Path) {
+	eprintln!(
+		"note: {} opts into indirect branch tracking, so only jump and call gadgets starting with \
+		 `endbr` are reported - use `--ibt false` to report every gadget",
+		path.display()
+	);
+}
+
+/// Finds the gadgets in a single file of many, describing each with `describe` along with
+/// whether the file opts into shadow stacks, and noting when the file turns on IBT if `note_ibt`
+fn search_file<T>(
+	path: &Path,
+	finder: &GadgetFinder,
+	section_headers: bool,
+	note_ibt: bool,
+	describe: impl Fn(&Gadget, usize, &[Section], bool) -> T,
+) -> error::Result<Vec<T>> {
+	let mut b = Binary::new(path)?;
+	b.set_section_headers(section_headers);
+	let sections = finder.sections(&b)?;
+	let gadgets = finder.find_in(&b, &sections)?;
+	if note_ibt && finder.ibt_enabled(&b) {
+		print_ibt_note(path);
+	}
+	let shadow_stack = shadow_stack_enabled(&b);
+	Ok(gadgets
+		.iter()
+		.map(|(gadget, address)| describe(gadget, *address, &sections, shadow_stack))
+		.collect())
+}
+
+/// Whether the file at `path` starts like an executable of a format that may be searched
+fn is_executable(path: &Path) -> bool {
+	File::open(path)
+		.ok()
+		.and_then(|mut file| goblin::peek(&mut file).ok())
+		.is_some_and(|hint| !matches!(hint, goblin::Hint::Unknown(_)))
+}
+
+/// Searches every file in parallel, skipping those which aren't executables and reporting those
+/// which can't be searched
+fn search_files<T: Send>(
+	files: &[PathBuf],
+	search: impl Fn(&Path) -> error::Result<Vec<T>> + Sync,
+) -> Vec<Vec<T>> {
+	files
+		.par_iter()
+		.filter(|path| is_executable(path))
+		.filter_map(|path| match search(path) {
+			Ok(found) => Some(found),
+			// Executables of an unsupported format or architecture are skipped
+			Err(error::Error::ParseErr | error::Error::Unsupported | error::Error::NoSlice(..)) => {
+				None
+			}
+			Err(e) => {
+				eprintln!("warning: skipping {}: {}", path.display(), e);
+				None
+			}
+		})
+		.collect()
+}
+
+/// Searches every file within `paths`, tagging each gadget with the file it was found in
+fn search_many(
+	paths: &[PathBuf],
+	finder: &GadgetFinder,
+	format: Format,
+	annotations: Annotations,
+	section_headers: bool,
+	note_ibt: bool,
+	start: Instant,
+) -> Result<(), Box<dyn Error>> {
+	let mut files = Vec::new();
+	for path in paths {
+		collect_files(path, &mut files);
+	}
+	files.sort();
+	files.dedup();
+
+	// Stdout uses a LineWriter internally, therefore we improve performance by wrapping stdout in a BufWriter
+	let mut stdout = BufWriter::new(stdout());
+
+	let (file_count, gadget_count, elapsed) = match format {
+		Format::Text => {
+			let found = search_files(&files, |path| {
+				let describe = |gadget: &Gadget, address, sections: &[Section], shstk| {
+					let annotations = Annotations { shadow_stack: shstk, ..annotations };
+					let mut output = ColourFormatter::new();
+					output.write(&format!("{}: ", path.display()), FormatterTextKind::Text);
+					format_gadget(&mut output, gadget, address, sections, annotations);
+					output.to_string()
+				};
+				search_file(path, finder, section_headers, note_ibt, describe)
+			});
+			// Don't account for time it takes to print gadgets since this depends on terminal implementation
+			let elapsed = Instant::now() - start;
+			for line in found.iter().flatten() {
+				if writeln!(stdout, "{}", line).is_err() {
+					break; // Pipe closed - finished writing gadgets
+				}
+			}
+			(found.len(), found.iter().map(Vec::len).sum(), elapsed)
+		}
+		Format::Json | Format::Jsonl | Format::Python => {
+			let found = search_files(&files, |path| {
+				let describe = |gadget: &Gadget, address, sections: &[Section], shstk| {
+					FileGadgetRecord {
+						file: path.to_path_buf(),
+						record: GadgetRecord::new(gadget, address, sections, shstk),
+					}
+				};
+				search_file(path, finder, section_headers, note_ibt, describe)
+			});
+			let elapsed = Instant::now() - start;
+			let records = found.iter().flatten().collect::<Vec<_>>();
+			if let Format::Json = format {
+				if serde_json::to_writer_pretty(&mut stdout, &records).is_ok() {
+					let _ = writeln!(stdout);
+				}
+			}
+			else {
+				for record in &records {
+					if serde_json::to_writer(&mut stdout, record).is_err()
+						|| writeln!(stdout).is_err()
+					{
+						break; // Pipe closed - finished writing gadgets
+					}
+				}
+			}
+			(found.len(), records.len(), elapsed)
+		}
+	};
+
+	drop(stdout);
+
+	eprintln!(
+		"\n==> Found {} gadgets in {} files in {:.3} seconds",
+		gadget_count,
+		file_count,
+		elapsed.as_secs_f32()
+	);
+
+	Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
 	let start = Instant::now();
 
 	let opts = Opt::parse();
 
-	let b = opts.binary;
-	let b = Binary::new(&b)?;
-	let sections = b.sections(opts.raw)?;
+	let multiple = opts.binary.len() > 1 || opts.binary.iter().any(|path| path.is_dir());
+	// Files of unknown formats are skipped rather than searched as raw code when searching many
+	let raw = match multiple {
+		true => opts.raw.or(Some(false)),
+		false => opts.raw,
+	};
 
-	let noisy = opts.noisy;
 	let colour = opts.colour;
-	let rop = !opts.norop;
-	let sys = !opts.nosys;
-	let jop = !opts.nojop;
-	let uniq = !opts.nouniq;
-	let stack_pivot = opts.stack_pivot;
-	let base_pivot = opts.base_pivot;
-	let max_instructions_per_gadget = opts.max_instr as usize;
-
-	if max_instructions_per_gadget == 0 {
+
+	if opts.max_instr == 0 {
 		panic!("Max instructions must be >0");
 	}
 
+	let cache_dir = match opts.cache_dir {
+		Some(cache_dir) => Some(cache_dir),
+		None if opts.cache => Some(default_cache_dir()?),
+		None => None,
+	};
+
+	let mut finder = GadgetFinder::new()
+		.raw(raw)
+		.slice(opts.slice.as_deref())
+		.cache_dir(cache_dir)
+		.noisy(opts.noisy)
+		.rop(!opts.norop)
+		.sys(!opts.nosys)
+		.jop(!opts.nojop)
+		.uniq(!opts.nouniq)
+		.stack_pivot(opts.stack_pivot)
+		.base_pivot(opts.base_pivot)
+		.epilogue(opts.epilogue)
+		.sigreturn(opts.sigreturn)
+		.ibt(opts.ibt)
+		.max_instructions(opts.max_instr as usize);
+
 	let ranges = opts
 		.range
 		.iter()
@@ -122,60 +648,342 @@ fn main() -> Result<(), Box<dyn Error>> {
 			Some((from, to))
 		})
 		.collect::<Vec<_>>();
+	for (from, to) in ranges {
+		finder = finder.range(from, to);
+	}
 
-	let regices = opts
-		.regex
-		.into_iter()
-		.map(|r| Regex::new(&r))
-		.collect::<Result<Vec<_>, _>>()?;
+	for section in &opts.section {
+		finder = finder.section(section);
+	}
 
-	let gadget_to_addr = sections
-		.iter()
-		.filter_map(Disassembly::new)
-		.flat_map(|dis| {
-			(0..dis.bytes().len())
-				.into_par_iter()
-				.filter(|offset| dis.is_tail_at(*offset, rop, sys, jop, noisy))
-				.flat_map_iter(|tail| {
-					dis.gadgets_from_tail(tail, max_instructions_per_gadget, noisy, uniq)
-				})
-				.collect::<Vec<_>>()
-		})
-		.filter(|&(_, address)| {
-			if ranges.is_empty() {
-				return true;
+	for function in &opts.function {
+		finder = finder.function(function);
+	}
+
+	for function in &opts.exclude_function {
+		finder = finder.exclude_function(function);
+	}
+
+	for query in &opts.query {
+		finder = finder.query(GadgetFilter::parse(query)?);
+	}
+
+	for regex in &opts.regex {
+		finder = finder.regex(Regex::new(regex)?);
+	}
+
+	if let Some(bad_bytes) = opts.bad_bytes.as_deref() {
+		finder = finder.bad_bytes(&parse_bytes(bad_bytes)?, opts.bad_bytes_encoding);
+	}
+
+	let mut annotations = Annotations {
+		effects: opts.effects,
+		symbols: opts.symbols,
+		sections: opts.section_headers,
+		shadow_stack: false,
+	};
+
+	if multiple {
+		let single_only = opts.diff.is_some()
+			|| opts.chain.is_some()
+			|| opts.srop.is_some()
+			|| opts.patterns
+			|| opts.base.is_some()
+			|| opts.stream
+			|| opts.mitigations
+			|| matches!(opts.format, Format::Python);
+		if single_only {
+			return Err("`--diff`, `--chain`, `--srop`, `--patterns`, `--base`, `--stream`, \
+			            `--mitigations` and python output need a single binary"
+				.into());
+		}
+		if let Some(colour) = colour {
+			set_override(colour);
+		}
+		return search_many(
+			&opts.binary,
+			&finder,
+			opts.format,
+			annotations,
+			opts.section_headers,
+			opts.ibt.is_none(),
+			start,
+		);
+	}
+
+	if opts.pid.is_some() && (opts.diff.is_some() || opts.base.is_some() || opts.mitigations) {
+		return Err("`--diff`, `--base` and `--mitigations` can't be used with `--pid`".into());
+	}
+	// Mappings read from a process carry no symbol tables to resolve functions from
+	if opts.pid.is_some()
+		&& (opts.symbols || !opts.function.is_empty() || !opts.exclude_function.is_empty())
+	{
+		return Err(
+			"`--symbols`, `--function` and `--exclude-function` can't be used with `--pid`".into(),
+		);
+	}
+	let process = opts.pid.map(Process::new).transpose()?;
+	let b = match &process {
+		Some(_) => None,
+		None => {
+			let mut b = Binary::new(&opts.binary[0])?;
+			b.set_section_headers(opts.section_headers);
+			if opts.base.is_some() {
+				if let Ok(false) = b.is_position_independent() {
+					eprintln!(
+						"warning: {} is not position independent, so rebased addresses may be wrong",
+						b.path().display()
+					);
+				}
+				b.set_base(opts.base);
 			}
-			ranges
-				.iter()
-				.any(|(from, to)| -> bool { *from <= address && address <= *to })
-		})
-		.collect::<HashMap<_, _>>();
+			Some(b)
+		}
+	};
+	let sections = match (&b, &process) {
+		(Some(b), _) => finder.sections(b)?,
+		(None, process) => process
+			.iter()
+			.flat_map(Process::sections)
+			.filter(|section| finder.searches(section))
+			.collect(),
+	};
+	// Resolve whether IBT is enabled here so that it also applies when streaming
+	if let Some(b) = &b {
+		let ibt = finder.ibt_enabled(b);
+		if ibt && opts.ibt.is_none() {
+			print_ibt_note(b.path());
+		}
+		annotations.shadow_stack = shadow_stack_enabled(b);
+		finder = finder.ibt(Some(ibt));
+	}
+	// The memory of a process is already in hand, so there is no binary to index
+	let find = || match &b {
+		Some(b) => finder.find_in(b, &sections),
+		None => Ok(finder.find_in_sections(&sections)),
+	};
 
-	let mut gadgets = gadget_to_addr
-		.into_iter()
-		.filter(|(g, _)| {
-			let mut formatted = String::new();
-			g.format_instruction(&mut formatted);
-			regices.iter().all(|r| r.is_match(&formatted))
-		})
-		.filter(|(g, _)| !stack_pivot | g.is_stack_pivot())
-		.filter(|(g, _)| !base_pivot | g.is_base_pivot())
-		.collect::<Vec<_>>();
-	gadgets.sort_unstable_by(|(_, addr1), (_, addr2)| addr1.cmp(addr2));
+	if let (Some(other), Some(b)) = (opts.diff, &b) {
+		if opts.nouniq {
+			return Err("`--diff` can't be used with `--nouniq`".into());
+		}
+		let mut other = Binary::new(&other)?;
+		other.set_base(opts.base);
+		other.set_section_headers(opts.section_headers);
+		let other_sections = finder.sections(&other)?;
+		let diff = GadgetDiff::new(
+			finder.find_in(b, &sections)?,
+			finder.find_in(&other, &other_sections)?,
+		);
+		let elapsed = Instant::now() - start;
 
-	let gadget_count = gadgets.len();
+		if let Some(colour) = colour {
+			set_override(colour);
+		}
+		let mut stdout = BufWriter::new(stdout());
+		match opts.format {
+			Format::Text => write_diff(&mut stdout, &diff),
+			Format::Json => {
+				let records = diff.entries().iter().map(DiffRecord::new).collect::<Vec<_>>();
+				serde_json::to_writer_pretty(&mut stdout, &records)?;
+				writeln!(stdout)?;
+			}
+			Format::Jsonl => {
+				for entry in diff.entries() {
+					serde_json::to_writer(&mut stdout, &DiffRecord::new(entry))?;
+					writeln!(stdout)?;
+				}
+			}
+			Format::Python => return Err("python output is not supported for diffs".into()),
+		}
+		drop(stdout);
 
-	// Don't account for time it takes to print gadgets since this depends on terminal implementation
-	let elapsed = Instant::now() - start;
+		eprintln!(
+			"\n==> Found {} changed and {} unchanged gadgets in {:.3} seconds",
+			diff.entries().len(),
+			diff.unchanged(),
+			elapsed.as_secs_f32()
+		);
+		return Ok(());
+	}
 
-	// Stdout uses a LineWriter internally, therefore we improve performance by wrapping stdout in a BufWriter
-	let mut stdout = BufWriter::new(stdout());
+	if let Some(goal) = opts.chain {
+		let gadgets = find()?;
+		let bitness = Bitness::of(&sections);
+		let goal = Goal::parse(&goal, bitness)?;
+		let chain = Chain::build(&goal, &gadgets, bitness)?;
+		let mut stdout = BufWriter::new(stdout());
+		match opts.format {
+			Format::Text => write!(stdout, "{}", chain)?,
+			Format::Json => {
+				serde_json::to_writer_pretty(&mut stdout, chain.links())?;
+				writeln!(stdout)?;
+			}
+			Format::Jsonl => {
+				for link in chain.links() {
+					serde_json::to_writer(&mut stdout, link)?;
+					writeln!(stdout)?;
+				}
+			}
+			Format::Python => chain.write_python(&mut stdout)?,
+		}
+		return Ok(());
+	}
+
+	if let Some(frame) = opts.srop {
+		let gadgets = find()?;
+		let bitness = Bitness::of(&sections);
+		let frame = SigreturnFrame::parse(&frame, bitness)?;
+		// Prefer the gadget with the fewest instructions in the way
+		let sigreturn = gadgets
+			.iter()
+			.filter(|(g, _)| g.is_sigreturn())
+			.min_by_key(|(g, address)| (g.len(), *address))
+			.map(|(gadget, address)| (gadget, *address));
+		if sigreturn.is_none() {
+			eprintln!(
+				"warning: no sigreturn gadget found - load its number into eax before a syscall \
+				 instead"
+			);
+		}
+		let mut stdout = BufWriter::new(stdout());
+		match opts.format {
+			Format::Text => {
+				if let Some((gadget, address)) = sigreturn {
+					let mut comment = String::new();
+					gadget.format_instruction(&mut comment);
+					writeln!(stdout, "{:#010x}: {}", address, comment)?;
+					writeln!(stdout)?;
+				}
+				write!(stdout, "{}", frame)?;
+			}
+			Format::Json | Format::Jsonl => {
+				let record = FrameRecord::new(&frame, sigreturn.map(|(_, address)| address));
+				match opts.format {
+					Format::Json => serde_json::to_writer_pretty(&mut stdout, &record)?,
+					_ => serde_json::to_writer(&mut stdout, &record)?,
+				}
+				writeln!(stdout)?;
+			}
+			Format::Python => frame.write_python(&mut stdout, sigreturn)?,
+		}
+		return Ok(());
+	}
+
+	if opts.patterns {
+		let patterns = find_patterns(&sections);
+		let elapsed = Instant::now() - start;
+
+		if let Some(colour) = colour {
+			set_override(colour);
+		}
+		let mut stdout = BufWriter::new(stdout());
+		match opts.format {
+			Format::Text => write_patterns(&mut stdout, &patterns, &sections, annotations),
+			Format::Json => {
+				let records = patterns
+					.iter()
+					.map(|pattern| PatternRecord::new(pattern, &sections, annotations.shadow_stack))
+					.collect::<Vec<_>>();
+				serde_json::to_writer_pretty(&mut stdout, &records)?;
+				writeln!(stdout)?;
+			}
+			Format::Jsonl => {
+				for pattern in &patterns {
+					let record = PatternRecord::new(pattern, &sections, annotations.shadow_stack);
+					serde_json::to_writer(&mut stdout, &record)?;
+					writeln!(stdout)?;
+				}
+			}
+			Format::Python => return Err("python output is not supported for patterns".into()),
+		}
+		drop(stdout);
+
+		eprintln!(
+			"\n==> Found {} patterns in {:.3} seconds",
+			patterns.len(),
+			elapsed.as_secs_f32()
+		);
+		return Ok(());
+	}
 
 	if let Some(colour) = colour {
 		set_override(colour);
 	}
 
-	write_gadgets(&mut stdout, &gadgets);
+	let mitigations = match &b {
+		Some(b) if opts.mitigations => Some(b.mitigations()?),
+		_ => None,
+	};
+
+	// Stdout uses a LineWriter internally, therefore we improve performance by wrapping stdout in a BufWriter
+	let mut stdout = BufWriter::new(stdout());
+
+	// Structured output carries the mitigations alongside the gadgets instead
+	if let Some(mitigations) = &mitigations {
+		match opts.format {
+			Format::Text => writeln!(stdout, "{}", mitigations)?,
+			Format::Python => {
+				for line in mitigations.to_string().lines() {
+					writeln!(stdout, "# {}", line)?;
+				}
+				writeln!(stdout)?;
+			}
+			Format::Json | Format::Jsonl => (),
+		}
+	}
+
+	let (gadget_count, elapsed) = if opts.stream {
+		// Gadgets are printed as they are found, so printing time can't be left out here
+		let gadgets = finder.stream(&sections);
+		let gadget_count = match opts.format {
+			Format::Text => write_gadgets(&mut stdout, gadgets, &sections, annotations),
+			Format::Jsonl => write_gadgets_jsonl(
+				&mut stdout,
+				gadgets,
+				&sections,
+				annotations,
+				mitigations.as_ref(),
+			),
+			Format::Json | Format::Python => {
+				return Err("streaming is only supported for text and jsonl output".into())
+			}
+		};
+		(gadget_count, Instant::now() - start)
+	}
+	else {
+		let gadgets = find()?;
+		let gadget_count = gadgets.len();
+
+		// Don't account for time it takes to print gadgets since this depends on terminal implementation
+		let elapsed = Instant::now() - start;
+
+		match opts.format {
+			Format::Text => write_gadgets(&mut stdout, gadgets, &sections, annotations),
+			Format::Json => write_gadgets_json(
+				&mut stdout,
+				gadgets,
+				&sections,
+				annotations,
+				mitigations.as_ref(),
+			),
+			Format::Jsonl => write_gadgets_jsonl(
+				&mut stdout,
+				gadgets,
+				&sections,
+				annotations,
+				mitigations.as_ref(),
+			),
+			Format::Python => match &b {
+				Some(b) => {
+					write_python(&mut stdout, &gadgets, &sections, b)?;
+					gadgets.len()
+				}
+				None => return Err("python output is not supported for processes".into()),
+			},
+		};
+		(gadget_count, elapsed)
+	};
 
 	drop(stdout);
 
@@ -1,19 +1,110 @@
-use crate::error::{Error, Result};
-use goblin::{elf64::program_header::PF_X, pe::section_table::IMAGE_SCN_MEM_EXECUTE, Object};
+use crate::{
+	error::{Error, Result},
+	mitigations::Mitigations,
+	symbols::{Symbol, Symbols},
+};
+use goblin::{
+	elf::{
+		header::{
+			EM_386, EM_AARCH64, EM_ARM, EM_MIPS, EM_MIPS_RS3_LE, EM_PPC, EM_PPC64, EM_RISCV,
+			EM_X86_64, ET_CORE, ET_DYN, ET_REL,
+		},
+		note::{Note, NT_FILE},
+		section_header::{SHF_EXECINSTR, SHT_NOBITS},
+		Header,
+		Elf,
+	},
+	elf64::program_header::{PF_X, PT_LOAD},
+	mach::{
+		constants::{
+			cputype::{
+				get_arch_name_from_types, CPU_SUBTYPE_MASK, CPU_TYPE_ARM, CPU_TYPE_ARM64,
+				CPU_TYPE_ARM64_32, CPU_TYPE_POWERPC, CPU_TYPE_POWERPC64, CPU_TYPE_X86,
+				CPU_TYPE_X86_64,
+			},
+			S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS, VM_PROT_EXECUTE,
+		},
+		header::{MH_BUNDLE, MH_DYLIB, MH_OBJECT, MH_PIE},
+		Mach, MachO,
+	},
+	pe::{
+		header::{
+			COFF_MACHINE_ARM, COFF_MACHINE_ARM64, COFF_MACHINE_ARMNT, COFF_MACHINE_POWERPC,
+			COFF_MACHINE_POWERPCFP, COFF_MACHINE_R4000, COFF_MACHINE_THUMB, COFF_MACHINE_WCEMIPSV2,
+			COFF_MACHINE_X86, COFF_MACHINE_X86_64,
+		},
+		section_table::IMAGE_SCN_MEM_EXECUTE,
+		PE,
+	},
+	Object,
+};
 use std::{
 	fs::read,
 	path::{Path, PathBuf},
+	sync::Arc,
 };
 
+const EF_RISCV_RVC: u32 = 0x1;
+const IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE: u16 = 0x40;
+const PAGE_SIZE: usize = 0x1000;
+
 #[derive(Debug, Clone, Copy)]
 pub enum Bitness {
 	Bits32,
 	Bits64,
 }
 
+impl Bitness {
+	/// The bitness of the first of a set of sections, or 64-bit if there are none
+	pub fn of(sections: &[Section]) -> Self {
+		sections
+			.first()
+			.map(Section::bitness)
+			.unwrap_or(Bitness::Bits64)
+	}
+
+	/// The size of an address or stack slot in bytes
+	pub fn word_size(self) -> usize {
+		match self {
+			Bitness::Bits64 => 8,
+			Bitness::Bits32 => 4,
+		}
+	}
+
+	/// The pwntools function which packs a word, such as `p64`
+	pub fn pack_fn(self) -> &'static str {
+		match self {
+			Bitness::Bits64 => "p64",
+			Bitness::Bits32 => "p32",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum Arch {
+	X86,
+	AArch64,
+	Arm,
+	Thumb,
+	/// `compressed` is set when the C extension allows 2-byte instructions
+	RiscV {
+		compressed: bool,
+	},
+	Mips,
+	PowerPC,
+}
+
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum Endian {
+	Little,
+	Big,
+}
+
 pub struct Binary {
 	path: PathBuf,
 	bytes: Vec<u8>,
+	base: Option<usize>,
+	section_headers: bool,
 }
 
 impl Binary {
@@ -21,147 +112,510 @@ impl Binary {
 		let path = path.as_ref();
 		let bytes = read(path)?;
 		let path = path.to_path_buf();
-		Ok(Self { path, bytes })
+		Ok(Self {
+			path,
+			bytes,
+			base: None,
+			section_headers: false,
+		})
 	}
 
 	pub fn path(&self) -> &Path { &self.path }
 
-	pub fn sections(&self, raw: Option<bool>) -> Result<Vec<Section>> {
-		match raw {
-			Some(true) => Ok(vec![Section {
-				file_offset: 0,
-				section_vaddr: 0,
-				program_base: 0,
-				bytes: &self.bytes,
-				bitness: Bitness::Bits64,
-			}]),
+	pub fn bytes(&self) -> &[u8] { &self.bytes }
+
+	pub fn base(&self) -> Option<usize> { self.base }
+
+	/// Relocates the binary so that its lowest loaded address is at `base` - reported addresses
+	/// are those the binary was linked at if this is `None`
+	pub fn set_base(&mut self, base: Option<usize>) { self.base = base }
+
+	pub fn section_headers(&self) -> bool { self.section_headers }
+
+	/// Finds the executable sections of ELF binaries from their section headers rather than their
+	/// program headers, so that gadgets in eg. `.plt` can be told apart from those in `.text`
+	///
+	/// Section headers are always used for ELF binaries without program headers, such as
+	/// relocatable objects and kernel modules.
+	pub fn set_section_headers(&mut self, section_headers: bool) {
+		self.section_headers = section_headers
+	}
+
+	/// Whether the binary may be loaded at an arbitrary address, so that rebasing it is meaningful
+	pub fn is_position_independent(&self) -> Result<bool> {
+		let pie = match Object::parse(&self.bytes)? {
+			Object::Elf(e) => matches!(e.header.e_type, ET_DYN | ET_REL),
+			Object::PE(p) => p.header.optional_header.is_some_and(|header| {
+				header.windows_fields.dll_characteristics & IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE
+					!= 0
+			}),
+			Object::Mach(Mach::Binary(m)) => is_position_independent_macho(&m),
+			Object::Mach(Mach::Fat(fat)) => fat
+				.into_iter()
+				.all(|m| m.is_ok_and(|m| is_position_independent_macho(&m))),
+			_ => true,
+		};
+		Ok(pie)
+	}
+
+	/// Whether the file is an ELF binary, which pwntools can load as an `ELF`
+	pub fn is_elf(&self) -> bool { matches!(Object::parse(&self.bytes), Ok(Object::Elf(_))) }
+
+	/// The address of the first page an ELF binary is linked at, which is moved to the base
+	/// address when relocating it - zero for other formats
+	pub fn link_base(&self) -> usize {
+		match Object::parse(&self.bytes) {
+			Ok(Object::Elf(e)) => elf_link_base(&e),
+			_ => 0,
+		}
+	}
+
+	/// Reports the exploit mitigations the binary was built with - only the first slice of a
+	/// universal Mach-O binary is inspected
+	pub fn mitigations(&self) -> Result<Mitigations> {
+		let pie = self.is_position_independent()?;
+		let mitigations = match Object::parse(&self.bytes)? {
+			Object::Elf(e) => Mitigations::elf(&e, &self.bytes, pie),
+			Object::PE(p) => Mitigations::pe(&p, pie),
+			Object::Mach(Mach::Binary(m)) => Mitigations::macho(&m, pie),
+			Object::Mach(Mach::Fat(fat)) => match fat.into_iter().next() {
+				Some(m) => Mitigations::macho(&m?, pie),
+				None => return Err(Error::ParseErr),
+			},
+			_ => return Err(Error::Unsupported),
+		};
+		Ok(mitigations)
+	}
+
+	/// Finds the executable sections of the binary
+	///
+	/// `slice` selects a single architecture (eg. `x86_64` or `arm64`) from a universal Mach-O
+	/// binary - every supported slice is searched if it is `None`. It has no effect on other
+	/// formats.
+	pub fn sections(&self, raw: Option<bool>, slice: Option<&str>) -> Result<Vec<Section<'_>>> {
+		let sections = match raw {
+			Some(true) => Ok(vec![self.raw_section(Bitness::Bits64)]),
 			Some(false) => match Object::parse(&self.bytes)? {
-				Object::Elf(e) => {
-					let bitness = if e.is_64 {
-						Bitness::Bits64
-					}
-					else {
-						Bitness::Bits32
-					};
-					let sections = e
-						.program_headers
-						.iter()
-						.filter(|header| header.p_flags & PF_X != 0)
-						.map(|header| {
-							let start_offset = header.p_offset as usize;
-							let end_offset = start_offset + header.p_filesz as usize;
-							Section {
-								file_offset: start_offset,
-								section_vaddr: header.p_vaddr as usize,
-								program_base: 0,
-								bytes: &self.bytes[start_offset..end_offset],
-								bitness,
-							}
-						})
-						.collect::<Vec<_>>();
-					Ok(sections)
-				}
-				Object::PE(p) => {
-					let bitness = if p.is_64 {
-						Bitness::Bits64
-					}
-					else {
-						Bitness::Bits32
-					};
-					let sections = p
-						.sections
-						.iter()
-						.filter(|section| (section.characteristics & IMAGE_SCN_MEM_EXECUTE) != 0)
-						.map(|section| {
-							let start_offset = section.pointer_to_raw_data as usize;
-							let end_offset = start_offset + section.size_of_raw_data as usize;
-							Section {
-								file_offset: start_offset,
-								section_vaddr: section.virtual_address as usize,
-								program_base: p.image_base,
-								bytes: &self.bytes[start_offset..end_offset],
-								bitness,
-							}
-						})
-						.collect::<Vec<_>>();
-					Ok(sections)
-				}
+				Object::Elf(e) => self.elf_sections(&e),
+				Object::PE(p) => self.pe_sections(&p),
+				Object::Mach(m) => self.mach_sections(&m, slice),
 				Object::Unknown(_) => Err(Error::ParseErr),
 				_ => Err(Error::Unsupported),
 			},
 			// Default behaviour - fall back to raw if able
 			None => match Object::parse(&self.bytes)? {
-				Object::Elf(e) => {
-					let bitness = if e.is_64 {
-						Bitness::Bits64
-					}
-					else {
-						Bitness::Bits32
-					};
-					let sections = e
-						.program_headers
-						.iter()
-						.filter(|header| header.p_flags & PF_X != 0)
-						.map(|header| {
-							let start_offset = header.p_offset as usize;
-							let end_offset = start_offset + header.p_filesz as usize;
-							Section {
-								file_offset: start_offset,
-								section_vaddr: header.p_vaddr as usize,
-								program_base: 0,
-								bytes: &self.bytes[start_offset..end_offset],
-								bitness,
-							}
-						})
-						.collect::<Vec<_>>();
-					Ok(sections)
+				Object::Elf(e) => self.elf_sections(&e),
+				Object::PE(p) => self.pe_sections(&p),
+				Object::Mach(m) => self.mach_sections(&m, slice),
+				_ => Ok(vec![self.raw_section(Bitness::Bits32)]),
+			},
+		}?;
+		// Addresses within a section are computed without checking for overflow from here on
+		if let Some(section) = sections.iter().find(|section| section.end_address().is_none()) {
+			return Err(Error::Malformed(format!(
+				"section `{}` extends past the end of the address space",
+				section.name
+			)));
+		}
+		Ok(sections)
+	}
+
+	fn raw_section(&self, bitness: Bitness) -> Section<'_> {
+		Section {
+			name: "raw".to_string(),
+			file_offset: 0,
+			section_vaddr: 0,
+			program_base: self.base.unwrap_or(0),
+			bytes: &self.bytes,
+			bitness,
+			arch: Arch::X86,
+			endian: Endian::Little,
+			symbols: Arc::default(),
+		}
+	}
+
+	fn elf_sections(&self, e: &Elf) -> Result<Vec<Section<'_>>> {
+		let bitness = if e.is_64 {
+			Bitness::Bits64
+		}
+		else {
+			Bitness::Bits32
+		};
+		let arches = elf_arches(&e.header)?;
+		let endian = if e.little_endian {
+			Endian::Little
+		}
+		else {
+			Endian::Big
+		};
+		// The first page loaded is placed at the base address when relocating
+		let link_base = match self.base {
+			Some(_) => elf_link_base(e),
+			None => 0,
+		};
+		let symbols = Arc::new(Symbols::elf(e, link_base, self.base.unwrap_or(0)));
+		if self.section_headers || e.program_headers.is_empty() {
+			return Ok(self.elf_section_headers(e, bitness, arches, endian, link_base, symbols));
+		}
+		// Segments of core dumps are named after the file which was mapped there
+		let mapped_files = match e.header.e_type {
+			ET_CORE => self.core_mapped_files(e),
+			_ => Vec::new(),
+		};
+		let sections = e
+			.program_headers
+			.iter()
+			.enumerate()
+			.filter(|(_, header)| header.p_flags & PF_X != 0)
+			.flat_map(|(index, header)| arches.iter().map(move |&arch| (index, header, arch)))
+			.filter_map(|(index, header, arch)| {
+				// Core dumps are often truncated, so keep whatever part of a segment was written
+				let start_offset = header.p_offset as usize;
+				let end_offset = start_offset
+					.saturating_add(header.p_filesz as usize)
+					.min(self.bytes.len());
+				let bytes = self.bytes.get(start_offset..end_offset)?;
+				let vaddr = header.p_vaddr as usize;
+				let name = mapped_files
+					.iter()
+					.find(|(start, end, _)| (*start..*end).contains(&vaddr))
+					.map(|(_, _, name)| name.clone())
+					.unwrap_or_else(|| format!("LOAD{}", index));
+				Some(Section {
+					name,
+					file_offset: start_offset,
+					section_vaddr: vaddr.checked_sub(link_base)?,
+					program_base: self.base.unwrap_or(0),
+					bytes,
+					bitness,
+					arch,
+					endian,
+					symbols: symbols.clone(),
+				})
+			})
+			.collect();
+		Ok(sections)
+	}
+
+	/// The address range and path of each file mapped into the process a core dump was taken
+	/// of, as recorded by its `NT_FILE` note
+	fn core_mapped_files(&self, e: &Elf) -> Vec<(usize, usize, String)> {
+		let word_size = if e.is_64 { 8 } else { 4 };
+		let desc = elf_notes(e, &self.bytes)
+			.into_iter()
+			.find(|note| note.n_type == NT_FILE)
+			.map(|note| note.desc);
+		let desc = match desc {
+			Some(desc) => desc,
+			None => return Vec::new(),
+		};
+		let word = |index: usize| -> Option<usize> {
+			let bytes = desc.get(index * word_size..(index + 1) * word_size)?;
+			let mut padded = [0; 8];
+			let word = match e.little_endian {
+				true => {
+					padded[..word_size].copy_from_slice(bytes);
+					u64::from_le_bytes(padded)
 				}
-				Object::PE(p) => {
-					let bitness = if p.is_64 {
-						Bitness::Bits64
-					}
-					else {
-						Bitness::Bits32
-					};
-					let sections = p
-						.sections
-						.iter()
-						.filter(|section| (section.characteristics & IMAGE_SCN_MEM_EXECUTE) != 0)
-						.map(|section| {
-							let start_offset = section.pointer_to_raw_data as usize;
-							let end_offset = start_offset + section.size_of_raw_data as usize;
-							Section {
-								file_offset: start_offset,
-								section_vaddr: section.virtual_address as usize,
-								program_base: p.image_base,
-								bytes: &self.bytes[start_offset..end_offset],
-								bitness,
-							}
-						})
-						.collect::<Vec<_>>();
-					Ok(sections)
+				false => {
+					padded[8 - word_size..].copy_from_slice(bytes);
+					u64::from_be_bytes(padded)
 				}
-				_ => Ok(vec![Section {
-					file_offset: 0,
-					section_vaddr: 0,
-					program_base: 0,
-					bytes: &self.bytes,
-					bitness: Bitness::Bits32,
-				}]),
-			},
+			};
+			Some(word as usize)
+		};
+
+		// A count and page size are followed by `(start, end, file offset)` for each file, then
+		// the null-terminated path of each file
+		let count = match word(0) {
+			Some(count) => count,
+			None => return Vec::new(),
+		};
+		let names = count
+			.checked_mul(3)
+			.and_then(|words| words.checked_add(2)?.checked_mul(word_size))
+			.and_then(|start| desc.get(start..))
+			.unwrap_or_default()
+			.split(|&b| b == 0)
+			.map(String::from_utf8_lossy);
+		(0..count)
+			.zip(names)
+			.filter_map(|(n, name)| {
+				let start = word(2 + 3 * n)?;
+				let end = word(2 + 3 * n + 1)?;
+				Some((start, end, name.into_owned()))
+			})
+			.collect()
+	}
+
+	/// Finds the executable sections of an ELF binary from its section headers, naming each after
+	/// its section eg. `.plt` or `.text`
+	///
+	/// Relocatable objects aren't linked at an address, so their sections are placed at their
+	/// offsets within the file instead.
+	fn elf_section_headers(
+		&self,
+		e: &Elf,
+		bitness: Bitness,
+		arches: &'static [Arch],
+		endian: Endian,
+		link_base: usize,
+		symbols: Arc<Symbols>,
+	) -> Vec<Section<'_>> {
+		let relocatable = e.header.e_type == ET_REL;
+		e.section_headers
+			.iter()
+			.filter(|header| header.sh_flags & SHF_EXECINSTR as u64 != 0)
+			.filter(|header| header.sh_type != SHT_NOBITS)
+			.filter_map(|header| {
+				let start_offset = header.sh_offset as usize;
+				let end_offset = start_offset.checked_add(header.sh_size as usize)?;
+				let bytes = self.bytes.get(start_offset..end_offset)?;
+				let name = e.shdr_strtab.get_at(header.sh_name).unwrap_or_default();
+				let vaddr = match relocatable {
+					true => start_offset,
+					false => (header.sh_addr as usize).checked_sub(link_base)?,
+				};
+				Some((name, start_offset, vaddr, bytes))
+			})
+			.flat_map(|section| arches.iter().map(move |&arch| (section, arch)))
+			.map(|((name, file_offset, section_vaddr, bytes), arch)| Section {
+				name: name.to_string(),
+				file_offset,
+				section_vaddr,
+				program_base: self.base.unwrap_or(0),
+				bytes,
+				bitness,
+				arch,
+				endian,
+				symbols: symbols.clone(),
+			})
+			.collect()
+	}
+
+	fn pe_sections(&self, p: &PE) -> Result<Vec<Section<'_>>> {
+		let bitness = if p.is_64 {
+			Bitness::Bits64
+		}
+		else {
+			Bitness::Bits32
+		};
+		let arches: &[Arch] = match p.header.coff_header.machine {
+			COFF_MACHINE_X86 | COFF_MACHINE_X86_64 => &[Arch::X86],
+			COFF_MACHINE_ARM64 => &[Arch::AArch64],
+			COFF_MACHINE_ARM => &[Arch::Arm, Arch::Thumb],
+			// Windows on ARM only supports Thumb-2 code
+			COFF_MACHINE_ARMNT | COFF_MACHINE_THUMB => &[Arch::Thumb],
+			COFF_MACHINE_R4000 | COFF_MACHINE_WCEMIPSV2 => &[Arch::Mips],
+			COFF_MACHINE_POWERPC | COFF_MACHINE_POWERPCFP => &[Arch::PowerPC],
+			_ => return Err(Error::Unsupported),
+		};
+		let program_base = self.base.unwrap_or(p.image_base);
+		let symbols = Arc::new(Symbols::pe(p, program_base));
+		let sections = p
+			.sections
+			.iter()
+			.filter(|section| (section.characteristics & IMAGE_SCN_MEM_EXECUTE) != 0)
+			.flat_map(|section| arches.iter().map(move |&arch| (section, arch)))
+			.filter_map(|(section, arch)| {
+				// Keep whatever part of a section lies within a truncated file
+				let start_offset = section.pointer_to_raw_data as usize;
+				let end_offset = start_offset
+					.saturating_add(section.size_of_raw_data as usize)
+					.min(self.bytes.len());
+				Some(Section {
+					name: section.name().unwrap_or_default().to_string(),
+					file_offset: start_offset,
+					section_vaddr: section.virtual_address as usize,
+					program_base,
+					bytes: self.bytes.get(start_offset..end_offset)?,
+					bitness,
+					arch,
+					endian: Endian::Little,
+					symbols: symbols.clone(),
+				})
+			})
+			.collect();
+		Ok(sections)
+	}
+
+	fn mach_sections<'b>(&'b self, m: &Mach<'b>, slice: Option<&str>) -> Result<Vec<Section<'b>>> {
+		let fat = match m {
+			Mach::Binary(m) => return self.macho_sections(m, 0, None),
+			Mach::Fat(fat) => fat,
+		};
+		let arches = fat.arches()?;
+		let names = arches
+			.iter()
+			.map(|arch| {
+				get_arch_name_from_types(arch.cputype, arch.cpusubtype & !CPU_SUBTYPE_MASK)
+					.map(str::to_string)
+					.unwrap_or_else(|| format!("cputype{}", arch.cputype))
+			})
+			.collect::<Vec<_>>();
+		if let Some(slice) = slice {
+			if !names.iter().any(|name| name == slice) {
+				return Err(Error::NoSlice(slice.to_string(), names.join(", ")));
+			}
 		}
+
+		let mut sections = Vec::new();
+		let mut supported = false;
+		for (index, (arch, name)) in arches.iter().zip(&names).enumerate() {
+			if slice.is_some_and(|slice| slice != name) {
+				continue;
+			}
+			let macho = fat.get(index)?;
+			match self.macho_sections(&macho, arch.offset as usize, Some(name)) {
+				Ok(found) => {
+					supported = true;
+					sections.extend(found);
+				}
+				// Slices for architectures which can't be searched are skipped unless requested
+				Err(Error::Unsupported) if slice.is_none() => (),
+				Err(e) => return Err(e),
+			}
+		}
+		if !supported {
+			return Err(Error::Unsupported);
+		}
+		Ok(sections)
 	}
+
+	/// `slice_offset` is the file offset of the Mach-O binary within a universal binary, and
+	/// `slice` its architecture name which is used to tell sections of different slices apart
+	fn macho_sections<'b>(
+		&'b self,
+		m: &MachO<'b>,
+		slice_offset: usize,
+		slice: Option<&str>,
+	) -> Result<Vec<Section<'b>>> {
+		let bitness = if m.is_64 {
+			Bitness::Bits64
+		}
+		else {
+			Bitness::Bits32
+		};
+		let arches: &[Arch] = match m.header.cputype() {
+			CPU_TYPE_X86 | CPU_TYPE_X86_64 => &[Arch::X86],
+			CPU_TYPE_ARM64 | CPU_TYPE_ARM64_32 => &[Arch::AArch64],
+			CPU_TYPE_ARM => &[Arch::Arm, Arch::Thumb],
+			CPU_TYPE_POWERPC | CPU_TYPE_POWERPC64 => &[Arch::PowerPC],
+			_ => return Err(Error::Unsupported),
+		};
+		let endian = if m.little_endian {
+			Endian::Little
+		}
+		else {
+			Endian::Big
+		};
+		// Segments are placed relative to the first mapped segment, skipping `__PAGEZERO`
+		let link_base = match self.base {
+			Some(_) => m
+				.segments
+				.iter()
+				.filter(|segment| segment.initprot != 0)
+				.map(|segment| segment.vmaddr as usize)
+				.min()
+				.unwrap_or(0),
+			None => 0,
+		};
+		let mut sections = Vec::new();
+		for segment in m
+			.segments
+			.iter()
+			.filter(|segment| segment.initprot & VM_PROT_EXECUTE != 0)
+		{
+			for (section, data) in segment.sections()? {
+				if section.flags & (S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS) == 0 {
+					continue;
+				}
+				let name = format!(
+					"{},{}",
+					section.segname().unwrap_or_default(),
+					section.name().unwrap_or_default()
+				);
+				let name = match slice {
+					Some(slice) => format!("{}:{}", slice, name),
+					None => name,
+				};
+				let section_vaddr = match (section.addr as usize).checked_sub(link_base) {
+					Some(section_vaddr) => section_vaddr,
+					None => continue,
+				};
+				sections.extend(arches.iter().map(|&arch| Section {
+					name: name.clone(),
+					file_offset: slice_offset + section.offset as usize,
+					section_vaddr,
+					program_base: self.base.unwrap_or(0),
+					bytes: data,
+					bitness,
+					arch,
+					endian,
+					symbols: Arc::default(),
+				}));
+			}
+		}
+		Ok(sections)
+	}
+}
+
+/// The address of the first page loaded from an ELF binary, or zero if it has no loaded segments
+fn elf_link_base(e: &Elf) -> usize {
+	e.program_headers
+		.iter()
+		.filter(|header| header.p_type == PT_LOAD)
+		.map(|header| header.p_vaddr as usize & !(PAGE_SIZE - 1))
+		.min()
+		.unwrap_or(0)
+}
+
+/// The architectures which the code of an ELF file may be in
+pub(crate) fn elf_arches(header: &Header) -> Result<&'static [Arch]> {
+	// 32-bit ARM code may be in either ARM or Thumb mode, so search both interpretations
+	let arches: &[Arch] = match header.e_machine {
+		EM_386 | EM_X86_64 => &[Arch::X86],
+		EM_AARCH64 => &[Arch::AArch64],
+		EM_ARM => &[Arch::Arm, Arch::Thumb],
+		EM_RISCV if header.e_flags & EF_RISCV_RVC != 0 => &[Arch::RiscV { compressed: true }],
+		EM_RISCV => &[Arch::RiscV { compressed: false }],
+		EM_MIPS | EM_MIPS_RS3_LE => &[Arch::Mips],
+		EM_PPC | EM_PPC64 => &[Arch::PowerPC],
+		_ => return Err(Error::Unsupported),
+	};
+	Ok(arches)
+}
+
+/// The notes of an ELF binary, read from its program headers or from its section headers if it
+/// has none
+///
+/// Reading stops at the first malformed note, as goblin doesn't skip past them.
+pub(crate) fn elf_notes<'a>(e: &Elf<'a>, bytes: &'a [u8]) -> Vec<Note<'a>> {
+	e.iter_note_headers(bytes)
+		.or_else(|| e.iter_note_sections(bytes, None))
+		.map(|notes| notes.map_while(|note| note.ok()).collect())
+		.unwrap_or_default()
+}
+
+fn is_position_independent_macho(m: &MachO) -> bool {
+	m.header.flags & MH_PIE != 0 || matches!(m.header.filetype, MH_DYLIB | MH_BUNDLE | MH_OBJECT)
 }
 
 pub struct Section<'b> {
-	file_offset: usize,
-	section_vaddr: usize,
-	program_base: usize,
-	bitness: Bitness,
-	bytes: &'b [u8],
+	pub(crate) name: String,
+	pub(crate) file_offset: usize,
+	pub(crate) section_vaddr: usize,
+	pub(crate) program_base: usize,
+	pub(crate) bitness: Bitness,
+	pub(crate) arch: Arch,
+	pub(crate) endian: Endian,
+	pub(crate) bytes: &'b [u8],
+	pub(crate) symbols: Arc<Symbols>,
 }
 
 impl Section<'_> {
+	pub fn name(&self) -> &str { &self.name }
+
 	pub fn file_offset(&self) -> usize { self.file_offset }
 
 	pub fn section_vaddr(&self) -> usize { self.section_vaddr }
@@ -170,5 +624,59 @@ impl Section<'_> {
 
 	pub fn bitness(&self) -> Bitness { self.bitness }
 
+	pub fn arch(&self) -> Arch { self.arch }
+
+	pub fn endian(&self) -> Endian { self.endian }
+
 	pub fn bytes(&self) -> &[u8] { self.bytes }
+
+	/// The functions of the binary this section belongs to
+	pub fn symbols(&self) -> &Symbols { &self.symbols }
+
+	/// The function within this section containing an address, along with the offset of the
+	/// address within it
+	pub fn symbol(&self, address: usize) -> Option<(&Symbol, usize)> {
+		self.symbols
+			.resolve(address)
+			.filter(|(symbol, _)| self.address_to_offset(symbol.address()).is_some())
+	}
+
+	/// Translates a virtual address into an offset within this section's bytes
+	pub fn address_to_offset(&self, address: usize) -> Option<usize> {
+		// Thumb addresses have the low bit set for interworking
+		let address = match self.arch {
+			Arch::Thumb => address & !1,
+			_ => address,
+		};
+		let offset = address.checked_sub(self.program_base + self.section_vaddr)?;
+		(offset < self.bytes.len()).then_some(offset)
+	}
+
+	/// The address just past the end of the section, or `None` if it lies past the end of the
+	/// address space
+	fn end_address(&self) -> Option<usize> {
+		self.program_base
+			.checked_add(self.section_vaddr)?
+			.checked_add(self.bytes.len())
+	}
+
+	/// The `len` bytes of code starting at a virtual address
+	pub fn bytes_at(&self, address: usize, len: usize) -> Option<&[u8]> {
+		let offset = self.address_to_offset(address)?;
+		self.bytes.get(offset..offset + len)
+	}
+
+	/// Encodes an address as a pointer would be stored in memory by this section's code
+	pub fn pack_address(&self, address: usize) -> Vec<u8> {
+		let address = address as u64;
+		let bytes = match self.endian {
+			Endian::Little => address.to_le_bytes(),
+			Endian::Big => address.to_be_bytes(),
+		};
+		let width = self.bitness.word_size();
+		match self.endian {
+			Endian::Little => bytes[..width].to_vec(),
+			Endian::Big => bytes[8 - width..].to_vec(),
+		}
+	}
 }
@@ -0,0 +1,655 @@
+use crate::{
+	binary::Bitness,
+	effects::{register_name, Effects},
+	error::{Error, Result},
+	gadgets::Gadget,
+	rules::{traps_in_user_mode, TailKind},
+};
+use iced_x86::{Instruction, Mnemonic, OpKind, Register};
+use serde::Serialize;
+use std::{
+	fmt::{Display, Formatter},
+	io::{self, Write},
+};
+
+/// Maximum number of alternative ways of setting a single register considered while ordering
+const MAX_CANDIDATES: usize = 4;
+
+/// The register state a chain should establish, optionally followed by a system call
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Goal {
+	registers: Vec<(Register, u64)>,
+	syscall: bool,
+}
+
+impl Goal {
+	pub fn new() -> Self { Self::default() }
+
+	pub fn set(mut self, register: Register, value: u64) -> Self {
+		self.registers.retain(|(r, _)| *r != register);
+		self.registers.push((register, value));
+		self
+	}
+
+	pub fn syscall(mut self) -> Self {
+		self.syscall = true;
+		self
+	}
+
+	/// `execve(path, NULL, NULL)`
+	pub fn execve(bitness: Bitness, path: u64) -> Self {
+		match bitness {
+			Bitness::Bits64 => Self::new()
+				.set(Register::RAX, 59)
+				.set(Register::RDI, path)
+				.set(Register::RSI, 0)
+				.set(Register::RDX, 0)
+				.syscall(),
+			Bitness::Bits32 => Self::new()
+				.set(Register::EAX, 11)
+				.set(Register::EBX, path)
+				.set(Register::ECX, 0)
+				.set(Register::EDX, 0)
+				.syscall(),
+		}
+	}
+
+	/// `mprotect(address, length, PROT_READ | PROT_WRITE | PROT_EXEC)`
+	pub fn mprotect(bitness: Bitness, address: u64, length: u64) -> Self {
+		match bitness {
+			Bitness::Bits64 => Self::new()
+				.set(Register::RAX, 10)
+				.set(Register::RDI, address)
+				.set(Register::RSI, length)
+				.set(Register::RDX, 7)
+				.syscall(),
+			Bitness::Bits32 => Self::new()
+				.set(Register::EAX, 125)
+				.set(Register::EBX, address)
+				.set(Register::ECX, length)
+				.set(Register::EDX, 7)
+				.syscall(),
+		}
+	}
+
+	/// Parses a goal such as `rdi=0x1000, rsi=0, rax=59; syscall`
+	///
+	/// Terms are separated by `,` or `;` and may be register assignments, `syscall`, or one of the
+	/// presets `execve(path)` and `mprotect(address, length)`.
+	pub fn parse(s: &str, bitness: Bitness) -> Result<Self> {
+		let mut goal = Self::new();
+		let mut rest = s.trim();
+		while !rest.is_empty() {
+			// Preset arguments contain commas, so take the whole parenthesised group
+			let end = match (rest.find('('), rest.find([',', ';'])) {
+				(Some(open), Some(sep)) if open < sep => rest[open..]
+					.find(')')
+					.map(|close| open + close + 1)
+					.ok_or_else(|| Error::InvalidGoal(rest.to_string()))?,
+				(_, Some(sep)) => sep,
+				(_, None) => rest.len(),
+			};
+			let term = rest[..end].trim();
+			rest = rest[end..].trim_start_matches([',', ';']).trim();
+			if term.is_empty() {
+				continue;
+			}
+			goal = goal.parse_term(term, bitness)?;
+		}
+		Ok(goal)
+	}
+
+	fn parse_term(self, term: &str, bitness: Bitness) -> Result<Self> {
+		let invalid = || Error::InvalidGoal(term.to_string());
+		if term == "syscall" {
+			return Ok(self.syscall());
+		}
+		if let Some((register, value)) = term.split_once('=') {
+			let register = parse_register(register.trim(), bitness).ok_or_else(invalid)?;
+			let value = parse_value(value.trim()).ok_or_else(invalid)?;
+			return Ok(self.set(register, value));
+		}
+		let (name, args) = term
+			.strip_suffix(')')
+			.and_then(|t| t.split_once('('))
+			.ok_or_else(invalid)?;
+		let args = args
+			.split(',')
+			.map(|a| parse_value(a.trim()))
+			.collect::<Option<Vec<_>>>()
+			.ok_or_else(invalid)?;
+		let preset = match (name.trim(), args.as_slice()) {
+			("execve", [path]) => Self::execve(bitness, *path),
+			("mprotect", [address, length]) => Self::mprotect(bitness, *address, *length),
+			_ => return Err(invalid()),
+		};
+		Ok(preset
+			.registers
+			.into_iter()
+			.fold(self, |goal, (r, v)| goal.set(r, v))
+			.syscall())
+	}
+
+	pub fn registers(&self) -> &[(Register, u64)] { &self.registers }
+
+	pub fn has_syscall(&self) -> bool { self.syscall }
+}
+
+fn parse_register(name: &str, bitness: Bitness) -> Option<Register> {
+	let name = name.to_lowercase();
+	Register::values()
+		.filter(|r| match bitness {
+			Bitness::Bits64 => r.is_gpr64(),
+			Bitness::Bits32 => r.is_gpr32(),
+		})
+		.filter(|r| !matches!(r, Register::RSP | Register::ESP))
+		.find(|r| register_name(*r) == name)
+}
+
+pub(crate) fn parse_value(s: &str) -> Option<u64> {
+	match s.strip_prefix("0x") {
+		Some(hex) => u64::from_str_radix(hex, 16).ok(),
+		None => s.parse().ok(),
+	}
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+	Gadget,
+	Value,
+	Padding,
+}
+
+/// A single stack slot of a chain
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct Link {
+	pub kind: LinkKind,
+	pub value: u64,
+	pub comment: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Chain {
+	#[serde(skip)]
+	bitness: Bitness,
+	links: Vec<Link>,
+}
+
+impl Chain {
+	/// Searches `gadgets` for a sequence which establishes the given goal
+	pub fn build(goal: &Goal, gadgets: &[(Gadget, usize)], bitness: Bitness) -> Result<Self> {
+		let word = bitness.word_size();
+		let shapes = gadgets
+			.iter()
+			.filter_map(|(gadget, address)| Shape::new(gadget, *address, word))
+			.collect::<Vec<_>>();
+
+		let candidates = goal
+			.registers
+			.iter()
+			.map(|&(target, value)| {
+				let mut steps = setters(&shapes, target, value, word);
+				steps.sort_by_key(|s| (s.links.len(), s.instructions, s.links[0].value));
+				steps.truncate(MAX_CANDIDATES);
+				if steps.is_empty() {
+					return Err(Error::NoChain(format!(
+						"no gadgets found to set {}",
+						register_name(target)
+					)));
+				}
+				Ok(steps)
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		let mut order = Vec::new();
+		let mut used = vec![false; candidates.len()];
+		if !order_steps(&candidates, &mut used, &mut order) {
+			return Err(Error::NoChain(
+				"every ordering of gadgets clobbers an earlier register".to_string(),
+			));
+		}
+
+		let mut links = order
+			.into_iter()
+			.flat_map(|(target, candidate)| candidates[target][candidate].links.clone())
+			.collect::<Vec<_>>();
+
+		if goal.syscall {
+			let (gadget, address) = gadgets
+				.iter()
+				.filter(|(g, _)| is_syscall_gadget(g, bitness))
+				.min_by_key(|(_, address)| *address)
+				.ok_or_else(|| Error::NoChain("no syscall gadget found".to_string()))?;
+			links.push(gadget_link(gadget, *address));
+		}
+
+		Ok(Self { bitness, links })
+	}
+
+	pub fn links(&self) -> &[Link] { &self.links }
+
+	pub fn bitness(&self) -> Bitness { self.bitness }
+
+	/// Writes the chain as a Python snippet using pwntools' packing helpers
+	pub fn write_python(&self, mut w: impl Write) -> io::Result<()> {
+		let pack = self.bitness.pack_fn();
+		writeln!(w, "from pwn import {}", pack)?;
+		writeln!(w)?;
+		writeln!(w, "chain = b\"\"")?;
+		for link in &self.links {
+			writeln!(
+				w,
+				"chain += {}({:#x})  # {}",
+				pack, link.value, link.comment
+			)?;
+		}
+		Ok(())
+	}
+}
+
+impl Display for Chain {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		// Zero padded to a full word, including the `0x` prefix
+		let width = 2 + 2 * self.bitness.word_size();
+		for link in &self.links {
+			writeln!(
+				f,
+				"{:#0width$x}  {}",
+				link.value,
+				link.comment,
+				width = width
+			)?;
+		}
+		Ok(())
+	}
+}
+
+/// A ret-terminated gadget without memory accesses, described by the stack slots it consumes
+struct Shape<'g> {
+	gadget: &'g Gadget,
+	address: usize,
+	/// Register loaded from each stack slot, or `None` for slots which are skipped
+	slots: Vec<Option<Register>>,
+	/// Index of the instruction which loads each slot
+	slot_instructions: Vec<usize>,
+}
+
+impl<'g> Shape<'g> {
+	fn new(gadget: &'g Gadget, address: usize, word: usize) -> Option<Self> {
+		let effects = gadget.effects();
+		if gadget.tail_kind() != Some(TailKind::Ret) || effects.memory_read || effects.memory_write
+		{
+			return None;
+		}
+		let (tail, head) = gadget.instructions().split_last()?;
+		if tail.op_count() != 0 {
+			return None;
+		}
+		let mut slots = Vec::new();
+		let mut slot_instructions = Vec::new();
+		if head.iter().any(traps_in_user_mode) {
+			return None;
+		}
+		for (index, instr) in head.iter().enumerate() {
+			match instr.mnemonic() {
+				Mnemonic::Pop => {
+					let reg = instr.op0_register();
+					if instr.op0_kind() != OpKind::Register
+						|| reg.size() != word
+						|| matches!(reg, Register::RSP | Register::ESP)
+					{
+						return None;
+					}
+					slots.push(Some(reg));
+					slot_instructions.push(index);
+				}
+				Mnemonic::Add
+					if matches!(instr.op0_register(), Register::RSP | Register::ESP)
+						&& instr.op0_kind() == OpKind::Register =>
+				{
+					let skip = usize::try_from(instr.try_immediate(1).ok()?).ok()?;
+					if skip % word != 0 {
+						return None;
+					}
+					for _ in 0..skip / word {
+						slots.push(None);
+						slot_instructions.push(index);
+					}
+				}
+				_ if instr.is_stack_instruction() => return None,
+				_ => (),
+			}
+		}
+		// Anything else which touched the stack pointer makes the layout unpredictable
+		let expected = ((slots.len() + 1) * word) as i64;
+		if effects.stack_delta != Some(expected) {
+			return None;
+		}
+		Some(Self {
+			gadget,
+			address,
+			slots,
+			slot_instructions,
+		})
+	}
+
+	/// Registers written by the instructions after index `after`
+	fn written_after(&self, after: usize) -> Vec<Register> {
+		let effects = Effects::new(&self.gadget.instructions()[after + 1..]);
+		effects.writes.into_iter().chain(effects.clobbers).collect()
+	}
+
+	fn written(&self) -> Vec<Register> {
+		let effects = self.gadget.effects();
+		effects.writes.into_iter().chain(effects.clobbers).collect()
+	}
+
+	/// Links for this gadget, placing `value` in any slot popped into `target`
+	fn links(&self, target: Option<(Register, u64)>, word: usize) -> Vec<Link> {
+		let padding = match word {
+			8 => 0x4141414141414141,
+			_ => 0x41414141,
+		};
+		let mut links = vec![gadget_link(self.gadget, self.address)];
+		links.extend(self.slots.iter().map(|slot| match (slot, target) {
+			(Some(r), Some((t, value))) if *r == t => Link {
+				kind: LinkKind::Value,
+				value,
+				comment: register_name(t),
+			},
+			(Some(r), _) => Link {
+				kind: LinkKind::Padding,
+				value: padding,
+				comment: format!("padding ({})", register_name(*r)),
+			},
+			(None, _) => Link {
+				kind: LinkKind::Padding,
+				value: padding,
+				comment: "padding".to_string(),
+			},
+		}));
+		links
+	}
+}
+
+/// One way of loading a value into a single register
+struct Step {
+	target: Register,
+	links: Vec<Link>,
+	/// Total number of instructions executed
+	instructions: usize,
+	clobbers: Vec<Register>,
+}
+
+/// Finds every way of loading `value` into `target`, either directly with a `pop` or by popping
+/// into another register which is then moved into `target`
+fn setters(shapes: &[Shape], target: Register, value: u64, word: usize) -> Vec<Step> {
+	let direct = |target: Register, value: u64| {
+		shapes
+			.iter()
+			.filter_map(move |shape| {
+				let slot = shape.slots.iter().rposition(|s| *s == Some(target))?;
+				if shape
+					.written_after(shape.slot_instructions[slot])
+					.contains(&target)
+				{
+					return None;
+				}
+				let mut clobbers = shape.written();
+				clobbers.retain(|r| *r != target);
+				Some(Step {
+					target,
+					links: shape.links(Some((target, value)), word),
+					instructions: shape.gadget.instructions().len(),
+					clobbers,
+				})
+			})
+			.collect::<Vec<_>>()
+	};
+
+	let mut steps = direct(target, value);
+	if !steps.is_empty() {
+		return steps;
+	}
+
+	for shape in shapes {
+		let source = match move_source(&shape.gadget.instructions()[0], target) {
+			Some(source) => source,
+			None => continue,
+		};
+		if shape.written_after(0).contains(&target) {
+			continue;
+		}
+		let load = match direct(source, value)
+			.into_iter()
+			.min_by_key(|s| (s.links.len(), s.instructions))
+		{
+			Some(load) => load,
+			None => continue,
+		};
+		let mut clobbers = load.clobbers;
+		clobbers.push(source);
+		clobbers.extend(shape.written());
+		clobbers.retain(|r| *r != target);
+		let mut links = load.links;
+		links.extend(shape.links(None, word));
+		steps.push(Step {
+			target,
+			links,
+			instructions: load.instructions + shape.gadget.instructions().len(),
+			clobbers,
+		});
+	}
+	steps
+}
+
+/// If `instr` copies another full register into `target`, returns that register
+fn move_source(instr: &Instruction, target: Register) -> Option<Register> {
+	if instr.op_count() != 2
+		|| instr.op0_kind() != OpKind::Register
+		|| instr.op1_kind() != OpKind::Register
+	{
+		return None;
+	}
+	let (op0, op1) = (instr.op0_register(), instr.op1_register());
+	match instr.mnemonic() {
+		Mnemonic::Mov if op0 == target => Some(op1),
+		Mnemonic::Xchg if op0 == target => Some(op1),
+		Mnemonic::Xchg if op1 == target => Some(op0),
+		_ => None,
+	}
+	.filter(|source| source.size() == target.size() && *source != target)
+}
+
+/// Depth-first search for an order of steps in which no step clobbers a register set before it
+fn order_steps(
+	candidates: &[Vec<Step>],
+	used: &mut [bool],
+	order: &mut Vec<(usize, usize)>,
+) -> bool {
+	if order.len() == candidates.len() {
+		return true;
+	}
+	for target in 0..candidates.len() {
+		if used[target] {
+			continue;
+		}
+		for (index, step) in candidates[target].iter().enumerate() {
+			let clobbers_earlier = order
+				.iter()
+				.any(|&(t, c)| step.clobbers.contains(&candidates[t][c].target));
+			if clobbers_earlier {
+				continue;
+			}
+			used[target] = true;
+			order.push((target, index));
+			if order_steps(candidates, used, order) {
+				return true;
+			}
+			order.pop();
+			used[target] = false;
+		}
+	}
+	false
+}
+
+fn is_syscall_gadget(gadget: &Gadget, bitness: Bitness) -> bool {
+	match (gadget.instructions(), bitness) {
+		([instr], Bitness::Bits64) => instr.mnemonic() == Mnemonic::Syscall,
+		([instr], Bitness::Bits32) => {
+			instr.mnemonic() == Mnemonic::Int && instr.try_immediate(0).ok() == Some(0x80)
+		}
+		_ => false,
+	}
+}
+
+fn gadget_link(gadget: &Gadget, address: usize) -> Link {
+	let mut comment = String::new();
+	gadget.format_instruction(&mut comment);
+	Link {
+		kind: LinkKind::Gadget,
+		value: address as u64,
+		comment,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use iced_x86::{Decoder, DecoderOptions};
+
+	const PADDING: u64 = 0x4141414141414141;
+
+	/// Decodes a 64-bit gadget from its encoding
+	fn gadget(bytes: &[u8], address: usize) -> (Gadget, usize) {
+		let instructions = Decoder::with_ip(64, bytes, address as u64, DecoderOptions::NONE)
+			.into_iter()
+			.collect();
+		(Gadget::x86(instructions, 0), address)
+	}
+
+	fn values(chain: &Chain) -> Vec<u64> { chain.links().iter().map(|link| link.value).collect() }
+
+	#[test]
+	fn parses_assignments_and_syscall() {
+		let goal = Goal::parse("rdi=0x1000, RSI = 2; rax=59; syscall", Bitness::Bits64).unwrap();
+		let expected = Goal::new()
+			.set(Register::RDI, 0x1000)
+			.set(Register::RSI, 2)
+			.set(Register::RAX, 59)
+			.syscall();
+		assert_eq!(goal, expected);
+	}
+
+	#[test]
+	fn parses_presets() {
+		let goal = Goal::parse("execve(0x1234)", Bitness::Bits64).unwrap();
+		assert_eq!(goal, Goal::execve(Bitness::Bits64, 0x1234));
+		let goal = Goal::parse("mprotect(0x1000, 0x2000)", Bitness::Bits32).unwrap();
+		assert_eq!(goal, Goal::mprotect(Bitness::Bits32, 0x1000, 0x2000));
+		assert_eq!(goal.registers()[0], (Register::EAX, 125));
+	}
+
+	#[test]
+	fn later_assignments_replace_earlier_ones() {
+		let goal = Goal::parse("rdi=1, rdi=2", Bitness::Bits64).unwrap();
+		assert_eq!(goal.registers(), &[(Register::RDI, 2)]);
+	}
+
+	#[test]
+	fn rejects_invalid_goals() {
+		for goal in ["rsp=1", "eax=1", "rdi", "rdi=zz", "execve(1, 2)", "open(1)"] {
+			assert!(
+				matches!(Goal::parse(goal, Bitness::Bits64), Err(Error::InvalidGoal(_))),
+				"{}",
+				goal
+			);
+		}
+	}
+
+	#[test]
+	fn pops_each_register() {
+		let gadgets = [
+			gadget(&[0x5f, 0xc3], 0x1000),             // pop rdi; ret
+			gadget(&[0x5e, 0x41, 0x5f, 0xc3], 0x2000), // pop rsi; pop r15; ret
+		];
+		let goal = Goal::parse("rdi=1, rsi=2", Bitness::Bits64).unwrap();
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64).unwrap();
+		assert_eq!(values(&chain), [0x1000, 1, 0x2000, 2, PADDING]);
+		assert_eq!(chain.links()[4].kind, LinkKind::Padding);
+	}
+
+	#[test]
+	fn moves_through_another_register() {
+		let gadgets = [
+			gadget(&[0x58, 0xc3], 0x1000),             // pop rax; ret
+			gadget(&[0x48, 0x89, 0xc7, 0xc3], 0x2000), // mov rdi, rax; ret
+		];
+		let goal = Goal::parse("rdi=5", Bitness::Bits64).unwrap();
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64).unwrap();
+		assert_eq!(values(&chain), [0x1000, 5, 0x2000]);
+	}
+
+	#[test]
+	fn orders_steps_around_clobbers() {
+		let gadgets = [
+			gadget(&[0x5f, 0x5e, 0xc3], 0x1000), // pop rdi; pop rsi; ret
+			gadget(&[0x5e, 0xc3], 0x2000),       // pop rsi; ret
+		];
+		let goal = Goal::parse("rsi=2, rdi=1", Bitness::Bits64).unwrap();
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64).unwrap();
+		assert_eq!(values(&chain), [0x1000, 1, PADDING, 0x2000, 2]);
+	}
+
+	#[test]
+	fn skips_stack_adjustments_by_a_register() {
+		let gadgets = [
+			gadget(&[0x48, 0x01, 0xc4, 0xc3], 0x1000), // add rsp, rax; ret
+			gadget(&[0x5f, 0xc3], 0x2000),             // pop rdi; ret
+		];
+		let goal = Goal::parse("rdi=1", Bitness::Bits64).unwrap();
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64).unwrap();
+		assert_eq!(values(&chain), [0x2000, 1]);
+	}
+
+	#[test]
+	fn skips_gadgets_which_trap() {
+		let mut gadgets = vec![
+			gadget(&[0x5a, 0xef, 0xc3], 0x1000), // pop rdx; out dx, eax; ret
+			gadget(&[0x5a, 0xf4, 0xc3], 0x2000), // pop rdx; hlt; ret
+			gadget(&[0x5a, 0xcc, 0xc3], 0x3000), // pop rdx; int3; ret
+			gadget(&[0x5a, 0xfa, 0xc3], 0x4000), // pop rdx; cli; ret
+			gadget(&[0x5a, 0x0f, 0x0b, 0xc3], 0x5000), // pop rdx; ud2; ret
+		];
+		let goal = Goal::parse("rdx=3", Bitness::Bits64).unwrap();
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64);
+		assert!(matches!(chain, Err(Error::NoChain(_))));
+
+		gadgets.push(gadget(&[0x5a, 0xc3], 0x6000)); // pop rdx; ret
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64).unwrap();
+		assert_eq!(values(&chain), [0x6000, 3]);
+	}
+
+	#[test]
+	fn appends_syscall() {
+		let gadgets = [
+			gadget(&[0x58, 0xc3], 0x1000), // pop rax; ret
+			gadget(&[0x0f, 0x05], 0x2000), // syscall
+		];
+		let goal = Goal::parse("rax=60; syscall", Bitness::Bits64).unwrap();
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64).unwrap();
+		assert_eq!(values(&chain), [0x1000, 60, 0x2000]);
+	}
+
+	#[test]
+	fn reports_unreachable_goals() {
+		let gadgets = [gadget(&[0x5f, 0xc3], 0x1000)];
+		let goal = Goal::parse("rsi=1", Bitness::Bits64).unwrap();
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64);
+		assert!(matches!(chain, Err(Error::NoChain(_))));
+		let goal = Goal::parse("rdi=1; syscall", Bitness::Bits64).unwrap();
+		let chain = Chain::build(&goal, &gadgets, Bitness::Bits64);
+		assert!(matches!(chain, Err(Error::NoChain(_))));
+	}
+}
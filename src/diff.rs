@@ -0,0 +1,94 @@
+use crate::{gadgets::Gadget, output::InstructionRecord};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+	Added,
+	Removed,
+	/// The same instructions are found at a different address
+	Relocated,
+}
+
+pub struct DiffEntry {
+	pub change: Change,
+	pub gadget: Gadget,
+	pub old_address: Option<usize>,
+	pub new_address: Option<usize>,
+}
+
+/// The gadgets which differ between two builds of a binary
+///
+/// Gadgets are matched by their instructions, so searches should be made with duplicates
+/// removed - each gadget is then compared at the lowest address it is found at.
+pub struct GadgetDiff {
+	entries: Vec<DiffEntry>,
+	unchanged: usize,
+}
+
+impl GadgetDiff {
+	pub fn new(old: Vec<(Gadget, usize)>, new: Vec<(Gadget, usize)>) -> Self {
+		let mut new = new.into_iter().collect::<HashMap<_, _>>();
+		let mut entries = Vec::new();
+		let mut unchanged = 0;
+		for (gadget, old_address) in old {
+			match new.remove(&gadget) {
+				Some(new_address) if new_address == old_address => unchanged += 1,
+				Some(new_address) => entries.push(DiffEntry {
+					change: Change::Relocated,
+					gadget,
+					old_address: Some(old_address),
+					new_address: Some(new_address),
+				}),
+				None => entries.push(DiffEntry {
+					change: Change::Removed,
+					gadget,
+					old_address: Some(old_address),
+					new_address: None,
+				}),
+			}
+		}
+		entries.extend(new.into_iter().map(|(gadget, new_address)| DiffEntry {
+			change: Change::Added,
+			gadget,
+			old_address: None,
+			new_address: Some(new_address),
+		}));
+		entries.sort_unstable_by_key(|entry| entry.old_address.or(entry.new_address));
+		Self { entries, unchanged }
+	}
+
+	/// Gadgets which were added, removed or relocated, ordered by their old address or the new
+	/// address of added gadgets
+	pub fn entries(&self) -> &[DiffEntry] { &self.entries }
+
+	/// The number of gadgets found at the same address in both builds
+	pub fn unchanged(&self) -> usize { self.unchanged }
+}
+
+/// Machine-readable description of a gadget which differs between two builds
+#[derive(Debug, Serialize)]
+pub struct DiffRecord {
+	pub change: Change,
+	pub old_address: Option<usize>,
+	pub new_address: Option<usize>,
+	pub instructions: Vec<InstructionRecord>,
+}
+
+impl DiffRecord {
+	pub fn new(entry: &DiffEntry) -> Self {
+		let instructions = entry
+			.gadget
+			.format_parts()
+			.into_iter()
+			.map(|(mnemonic, operands)| InstructionRecord { mnemonic, operands })
+			.collect();
+		Self {
+			change: entry.change,
+			old_address: entry.old_address,
+			new_address: entry.new_address,
+			instructions,
+		}
+	}
+}
@@ -1,9 +1,11 @@
 use crate::{
-	binary::{Bitness, Section},
-	gadgets::GadgetIterator,
+	binary::{Arch, Bitness, Section},
+	gadgets::{GadgetIterator, Gadgets, GenericGadgetIterator},
+	generic::{GenericDisassembler, GenericInstruction, MAX_GENERIC_INSTRUCTION_LENGTH},
 	rules::is_gadget_tail,
 };
 use iced_x86::{Decoder, DecoderOptions, Instruction};
+use std::ops::Range;
 
 const MAX_INSTRUCTION_LENGTH: usize = 15;
 
@@ -31,53 +33,144 @@ impl<'b> Disassembler<'b> {
 	}
 }
 
+enum Decoded {
+	X86(Vec<Instruction>),
+	/// Instructions at each multiple of `alignment` bytes
+	Generic {
+		alignment: usize,
+		instructions: Vec<Option<GenericInstruction>>,
+	},
+}
+
 pub struct Disassembly<'b> {
 	section: &'b Section<'b>,
 	bytes: &'b [u8],
-	instructions: Vec<Instruction>,
+	decoded: Decoded,
+	/// Offset within the section of the first decoded instruction
+	decoded_start: usize,
+	tails: Range<usize>,
 	file_offset: usize,
 }
 
 impl<'b> Disassembly<'b> {
 	pub fn new(section: &'b Section) -> Option<Self> {
+		let len = section.bytes().len();
+		Self::window(section, 0..len, len)
+	}
+
+	/// Disassembles only the part of a section needed to find gadgets with tails in `tails`,
+	/// which is `lookback` bytes before the tails and enough bytes after to decode any delay slots
+	///
+	/// See [`Disassembly::lookback`] for the lookback needed to find every gadget.
+	pub fn window(section: &'b Section, tails: Range<usize>, lookback: usize) -> Option<Self> {
 		let bytes = section.bytes();
 
-		if bytes.is_empty() {
+		let tails = tails.start.min(bytes.len())..tails.end.min(bytes.len());
+		if tails.is_empty() {
 			return None;
 		}
 
-		let mut instructions = vec![Instruction::default(); bytes.len()];
-		let mut disassembler = Disassembler::new(section.bitness(), bytes);
-
-		// Fully disassemble program - cache for later use when finding gadgets
-		instructions
-			.iter_mut()
-			.enumerate()
-			.for_each(|(n, instruction)| {
-				disassembler.decode_at_offset(
-					(section.program_base() + section.section_vaddr() + n) as u64,
-					n,
-					instruction,
-				)
-			});
+		let section_start = section.program_base() + section.section_vaddr();
+
+		let decoded_end = bytes.len().min(tails.end + MAX_INSTRUCTION_LENGTH);
+
+		let (decoded, decoded_start) = match section.arch() {
+			Arch::X86 => {
+				let decoded_start = tails.start.saturating_sub(lookback);
+				let mut instructions = vec![Instruction::default(); decoded_end - decoded_start];
+				let mut disassembler = Disassembler::new(section.bitness(), bytes);
+
+				// Fully disassemble window - cache for later use when finding gadgets
+				instructions
+					.iter_mut()
+					.zip(decoded_start..)
+					.for_each(|(instruction, n)| {
+						disassembler.decode_at_offset((section_start + n) as u64, n, instruction)
+					});
+				(Decoded::X86(instructions), decoded_start)
+			}
+			arch => {
+				let disassembler =
+					GenericDisassembler::new(arch, section.bitness(), section.endian())?;
+				let alignment = disassembler.alignment();
+				let decoded_start = tails.start.saturating_sub(lookback) / alignment * alignment;
+				let instructions = (decoded_start..decoded_end)
+					.step_by(alignment)
+					.map(|n| disassembler.decode(&bytes[n..], (section_start + n) as u64))
+					.collect();
+				let decoded = Decoded::Generic {
+					alignment,
+					instructions,
+				};
+				(decoded, decoded_start)
+			}
+		};
 
 		Some(Self {
 			section,
 			bytes,
-			instructions,
-			file_offset: section.program_base() + section.section_vaddr(),
+			decoded,
+			decoded_start,
+			tails,
+			file_offset: section_start,
 		})
 	}
 
+	/// The number of bytes before a tail which must be disassembled to find every gadget of up to
+	/// `max_instructions` instructions ending in it
+	pub fn lookback(max_instructions: usize) -> usize {
+		max_instructions.saturating_sub(1)
+			* MAX_INSTRUCTION_LENGTH.max(MAX_GENERIC_INSTRUCTION_LENGTH)
+	}
+
 	pub fn bytes(&self) -> &[u8] { self.bytes }
 
+	/// The offsets within the section which may be searched for tails
+	pub fn tails(&self) -> Range<usize> { self.tails.clone() }
+
 	pub fn file_offset(&self) -> usize { self.file_offset }
 
-	pub fn instruction(&self, index: usize) -> Option<&Instruction> { self.instructions.get(index) }
+	pub fn section(&self) -> &'b Section<'b> { self.section }
+
+	pub fn instruction(&self, index: usize) -> Option<&Instruction> {
+		match &self.decoded {
+			Decoded::X86(instructions) => instructions.get(index.checked_sub(self.decoded_start)?),
+			Decoded::Generic { .. } => None,
+		}
+	}
+
+	pub fn generic_instruction(&self, index: usize) -> Option<&GenericInstruction> {
+		match &self.decoded {
+			Decoded::X86(_) => None,
+			Decoded::Generic {
+				alignment,
+				instructions,
+			} => {
+				if !index.is_multiple_of(*alignment) {
+					return None;
+				}
+				let slot = index.checked_sub(self.decoded_start)? / alignment;
+				instructions.get(slot)?.as_ref()
+			}
+		}
+	}
 
 	pub fn is_tail_at(&self, index: usize, rop: bool, sys: bool, jop: bool, noisy: bool) -> bool {
-		let instruction = self.instructions[index];
-		is_gadget_tail(&instruction, rop, sys, jop, noisy)
+		match &self.decoded {
+			Decoded::X86(_) => self
+				.instruction(index)
+				.is_some_and(|i| is_gadget_tail(i, rop, sys, jop, noisy)),
+			Decoded::Generic { .. } => self
+				.generic_instruction(index)
+				.filter(|i| i.is_gadget_tail(rop, sys, jop))
+				.is_some_and(|i| {
+					// The delay slot executes as part of the gadget, so must be usable too
+					!i.has_delay_slot()
+						|| self
+							.generic_instruction(index + i.len())
+							.is_some_and(|d| d.is_rop_gadget_head(noisy))
+				}),
+		}
 	}
 
 	pub fn gadgets_from_tail(
@@ -86,20 +179,55 @@ impl<'b> Disassembly<'b> {
 		max_instructions: usize,
 		noisy: bool,
 		uniq: bool,
-	) -> GadgetIterator {
+	) -> Gadgets<'_> {
 		assert!(max_instructions > 0);
-		let start_index =
-			tail_index.saturating_sub((max_instructions - 1) * MAX_INSTRUCTION_LENGTH);
-		let predecessors = &self.instructions[start_index..tail_index];
-		let tail_instruction = self.instructions[tail_index];
-		GadgetIterator::new(
-			self.section.program_base() + self.section.section_vaddr(),
-			tail_instruction,
-			predecessors,
-			max_instructions,
-			noisy,
-			uniq,
-			start_index,
-		)
+		let section_start = self.section.program_base() + self.section.section_vaddr();
+		match &self.decoded {
+			Decoded::X86(instructions) => {
+				let start_index = tail_index
+					.saturating_sub((max_instructions - 1) * MAX_INSTRUCTION_LENGTH)
+					.max(self.decoded_start);
+				let predecessors = &instructions
+					[start_index - self.decoded_start..tail_index - self.decoded_start];
+				let tail_instruction = instructions[tail_index - self.decoded_start];
+				Gadgets::X86(GadgetIterator::new(
+					section_start,
+					tail_instruction,
+					predecessors,
+					max_instructions,
+					noisy,
+					uniq,
+					start_index,
+				))
+			}
+			Decoded::Generic {
+				alignment,
+				instructions,
+			} => {
+				let decoded_slot = self.decoded_start / alignment;
+				let tail_slot = tail_index / alignment;
+				let lookback = (max_instructions - 1) * MAX_GENERIC_INSTRUCTION_LENGTH / alignment;
+				let start_slot = tail_slot.saturating_sub(lookback).max(decoded_slot);
+				let tail_instruction = instructions[tail_slot - decoded_slot]
+					.clone()
+					.expect("no instruction at tail");
+				let delay_slot = tail_instruction
+					.has_delay_slot()
+					.then(|| self.generic_instruction(tail_index + tail_instruction.len()))
+					.flatten()
+					.cloned();
+				Gadgets::Generic(GenericGadgetIterator::new(
+					section_start,
+					*alignment,
+					tail_instruction,
+					delay_slot,
+					&instructions[start_slot - decoded_slot..tail_slot - decoded_slot],
+					max_instructions,
+					noisy,
+					uniq,
+					start_slot,
+				))
+			}
+		}
 	}
 }
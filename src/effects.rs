@@ -0,0 +1,218 @@
+use iced_x86::{
+	CodeSize, Instruction, InstructionInfoFactory, Mnemonic, OpAccess, OpKind, Register,
+};
+use serde::{Serialize, Serializer};
+use std::fmt::{Display, Formatter, Result};
+
+/// Summary of the architectural state a gadget depends on and modifies
+///
+/// The stack and instruction pointers are not listed as registers - changes to the stack
+/// pointer are described by `stack_delta` instead. Memory accesses made implicitly through
+/// the stack pointer (`push`, `pop`, `ret`, etc.) are not counted as memory reads or writes.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct Effects {
+	/// Registers whose value on entry is used by the gadget
+	#[serde(serialize_with = "serialize_registers")]
+	pub reads: Vec<Register>,
+	/// Registers written through an explicit operand
+	#[serde(serialize_with = "serialize_registers")]
+	pub writes: Vec<Register>,
+	/// Registers written as a side effect of an instruction rather than through an operand
+	#[serde(serialize_with = "serialize_registers")]
+	pub clobbers: Vec<Register>,
+	/// Net change of the stack pointer in bytes, or `None` if it cannot be determined statically
+	pub stack_delta: Option<i64>,
+	pub memory_read: bool,
+	pub memory_write: bool,
+	pub flags_modified: bool,
+}
+
+impl Effects {
+	pub fn new(instructions: &[Instruction]) -> Self {
+		let mut factory = InstructionInfoFactory::new();
+		let mut effects = Self {
+			stack_delta: Some(0),
+			..Self::default()
+		};
+
+		for instr in instructions {
+			let info = factory.info(instr);
+			let normalise = |r: Register| normalise(r, instr.code_size());
+			let explicit = (0..instr.op_count())
+				.filter(|&op| instr.op_kind(op) == OpKind::Register)
+				.filter(|&op| is_write(info.op_access(op)))
+				.map(|op| normalise(instr.op_register(op)))
+				.collect::<Vec<_>>();
+			let explicit_sp = explicit.iter().any(|&r| is_stack_pointer(r));
+			let writes_sp = info
+				.used_registers()
+				.iter()
+				.any(|used| is_stack_pointer(used.register()) && is_write(used.access()));
+
+			// Reads must be processed before writes so that e.g. `add rax, rbx` counts rax as an input
+			for used in info.used_registers() {
+				let reg = normalise(used.register());
+				if is_pointer(reg) || !is_read(used.access()) {
+					continue;
+				}
+				if !effects.writes.contains(&reg) && !effects.clobbers.contains(&reg) {
+					insert(&mut effects.reads, reg);
+				}
+			}
+			for used in info.used_registers() {
+				let reg = normalise(used.register());
+				if is_pointer(reg) || !is_write(used.access()) {
+					continue;
+				}
+				if explicit.contains(&reg) {
+					effects.clobbers.retain(|&r| r != reg);
+					insert(&mut effects.writes, reg);
+				}
+				else if !effects.writes.contains(&reg) {
+					insert(&mut effects.clobbers, reg);
+				}
+			}
+
+			for used in info.used_memory() {
+				let via_stack = instr.is_stack_instruction() && is_pointer(used.base());
+				if via_stack {
+					continue;
+				}
+				effects.memory_read |= is_read(used.access());
+				effects.memory_write |= is_write(used.access());
+			}
+
+			effects.flags_modified |= instr.rflags_modified() != 0;
+
+			effects.stack_delta = effects
+				.stack_delta
+				.and_then(|delta| stack_change(instr, writes_sp, explicit_sp).map(|d| delta + d));
+		}
+
+		effects
+	}
+}
+
+impl Display for Effects {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		let mut parts = Vec::new();
+		let registers = |regs: &[Register]| {
+			regs.iter()
+				.map(|r| register_name(*r))
+				.collect::<Vec<_>>()
+				.join(", ")
+		};
+		if !self.reads.is_empty() {
+			parts.push(format!("reads {}", registers(&self.reads)));
+		}
+		if !self.writes.is_empty() {
+			parts.push(format!("writes {}", registers(&self.writes)));
+		}
+		if !self.clobbers.is_empty() {
+			parts.push(format!("clobbers {}", registers(&self.clobbers)));
+		}
+		match self.stack_delta {
+			Some(delta) => parts.push(format!("stack {:+}", delta)),
+			None => parts.push("stack ?".to_string()),
+		}
+		match (self.memory_read, self.memory_write) {
+			(true, true) => parts.push("mem rw".to_string()),
+			(true, false) => parts.push("mem r".to_string()),
+			(false, true) => parts.push("mem w".to_string()),
+			(false, false) => (),
+		}
+		if self.flags_modified {
+			parts.push("flags".to_string());
+		}
+		write!(f, "{}", parts.join(" | "))
+	}
+}
+
+/// Change in the stack pointer caused by a single instruction, or `None` if the stack pointer
+/// is overwritten with a value which is not known statically
+fn stack_change(instr: &Instruction, writes_sp: bool, explicit_sp: bool) -> Option<i64> {
+	if !writes_sp {
+		return Some(0);
+	}
+	if explicit_sp {
+		// Writing esp in 64-bit code zeroes the upper half of rsp
+		let full_width = match instr.code_size() {
+			CodeSize::Code64 => instr.op0_register() == Register::RSP,
+			_ => instr.op0_register() == Register::ESP,
+		};
+		if !full_width {
+			return None;
+		}
+		let immediate = match instr.op1_kind() {
+			OpKind::Immediate8
+			| OpKind::Immediate16
+			| OpKind::Immediate32
+			| OpKind::Immediate64
+			| OpKind::Immediate8to16
+			| OpKind::Immediate8to32
+			| OpKind::Immediate8to64
+			| OpKind::Immediate32to64 => instr.immediate(1) as i64,
+			_ => return None,
+		};
+		return match instr.mnemonic() {
+			Mnemonic::Add if instr.op0_kind() == OpKind::Register => Some(immediate),
+			Mnemonic::Sub if instr.op0_kind() == OpKind::Register => Some(-immediate),
+			_ => None,
+		};
+	}
+	match instr.mnemonic() {
+		Mnemonic::Leave => None,
+		_ if instr.is_stack_instruction() => Some(instr.stack_pointer_increment() as i64),
+		_ => None,
+	}
+}
+
+/// Maps partial general purpose registers onto the full register for the code size
+fn normalise(reg: Register, code_size: CodeSize) -> Register {
+	if !reg.is_gpr() {
+		reg
+	}
+	else if code_size == CodeSize::Code64 {
+		reg.full_register()
+	}
+	else {
+		reg.full_register32()
+	}
+}
+
+fn insert(regs: &mut Vec<Register>, reg: Register) {
+	if !regs.contains(&reg) {
+		regs.push(reg)
+	}
+}
+
+fn is_read(access: OpAccess) -> bool {
+	matches!(
+		access,
+		OpAccess::Read | OpAccess::CondRead | OpAccess::ReadWrite | OpAccess::ReadCondWrite
+	)
+}
+
+fn is_write(access: OpAccess) -> bool {
+	matches!(
+		access,
+		OpAccess::Write | OpAccess::CondWrite | OpAccess::ReadWrite | OpAccess::ReadCondWrite
+	)
+}
+
+fn is_stack_pointer(reg: Register) -> bool {
+	matches!(reg, Register::RSP | Register::ESP | Register::SP)
+}
+
+fn is_pointer(reg: Register) -> bool {
+	is_stack_pointer(reg) || matches!(reg, Register::RIP | Register::EIP)
+}
+
+pub fn register_name(reg: Register) -> String { format!("{:?}", reg).to_lowercase() }
+
+fn serialize_registers<S: Serializer>(
+	regs: &[Register],
+	s: S,
+) -> std::result::Result<S::Ok, S::Error> {
+	s.collect_seq(regs.iter().map(|r| register_name(*r)))
+}
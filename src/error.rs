@@ -8,8 +8,22 @@ pub enum Error {
 	IoErr(#[from] std::io::Error),
 	#[error(transparent)]
 	GoblinErr(#[from] goblin::error::Error),
+	#[error(transparent)]
+	JsonErr(#[from] serde_json::Error),
 	#[error("unable to parse binary")]
 	ParseErr,
 	#[error("unsupported format or architecture")]
 	Unsupported,
+	#[error("malformed binary: {0}")]
+	Malformed(String),
+	#[error("no `{0}` slice in universal binary (found {1})")]
+	NoSlice(String, String),
+	#[error("invalid chain goal `{0}`")]
+	InvalidGoal(String),
+	#[error("unable to build chain: {0}")]
+	NoChain(String),
+	#[error("invalid query: {0}")]
+	InvalidQuery(String),
+	#[error("invalid sigreturn frame: {0}")]
+	InvalidFrame(String),
 }
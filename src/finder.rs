@@ -0,0 +1,464 @@
+use crate::{
+	binary::{Binary, Section},
+	disassembler::Disassembly,
+	error::Result,
+	gadgets::Gadget,
+	index::GadgetIndex,
+	query::GadgetFilter,
+	rules::TailKind,
+};
+use rayon::prelude::*;
+use regex::Regex;
+use std::{
+	collections::{hash_map::DefaultHasher, HashMap, HashSet},
+	hash::{Hash, Hasher},
+	path::PathBuf,
+	vec,
+};
+
+const DEFAULT_WINDOW_SIZE: usize = 0x10000;
+
+/// Searches the executable sections of a binary for gadgets
+///
+/// Gadgets are returned deduplicated and sorted by address, with the same defaults as the `ropr`
+/// command line tool.
+pub struct GadgetFinder {
+	raw: Option<bool>,
+	slice: Option<String>,
+	cache_dir: Option<PathBuf>,
+	rop: bool,
+	sys: bool,
+	jop: bool,
+	noisy: bool,
+	uniq: bool,
+	max_instructions: usize,
+	window_size: usize,
+	ranges: Vec<(usize, usize)>,
+	section_names: Vec<String>,
+	functions: Vec<String>,
+	excluded_functions: Vec<String>,
+	regices: Vec<Regex>,
+	queries: Vec<GadgetFilter>,
+	stack_pivot: bool,
+	base_pivot: bool,
+	epilogue: bool,
+	sigreturn: bool,
+	bad_bytes: Vec<u8>,
+	bad_bytes_encoding: bool,
+	ibt: Option<bool>,
+}
+
+impl Default for GadgetFinder {
+	fn default() -> Self {
+		Self {
+			raw: None,
+			slice: None,
+			cache_dir: None,
+			rop: true,
+			sys: true,
+			jop: true,
+			noisy: false,
+			uniq: true,
+			max_instructions: 6,
+			window_size: DEFAULT_WINDOW_SIZE,
+			ranges: Vec::new(),
+			section_names: Vec::new(),
+			functions: Vec::new(),
+			excluded_functions: Vec::new(),
+			regices: Vec::new(),
+			queries: Vec::new(),
+			stack_pivot: false,
+			base_pivot: false,
+			epilogue: false,
+			sigreturn: false,
+			bad_bytes: Vec::new(),
+			bad_bytes_encoding: false,
+			ibt: None,
+		}
+	}
+}
+
+impl GadgetFinder {
+	pub fn new() -> Self { Self::default() }
+
+	/// Treats the binary as a blob of code - see [`Binary::sections`]
+	pub fn raw(mut self, raw: Option<bool>) -> Self {
+		self.raw = raw;
+		self
+	}
+
+	/// Selects a single architecture from a universal Mach-O binary - see [`Binary::sections`]
+	pub fn slice(mut self, slice: Option<&str>) -> Self {
+		self.slice = slice.map(str::to_string);
+		self
+	}
+
+	/// Keeps a [`GadgetIndex`] of each binary searched in the given directory, so that searching
+	/// the same binary again skips disassembling it
+	pub fn cache_dir(mut self, cache_dir: Option<PathBuf>) -> Self {
+		self.cache_dir = cache_dir;
+		self
+	}
+
+	/// Includes normal gadgets ending in a return
+	pub fn rop(mut self, rop: bool) -> Self {
+		self.rop = rop;
+		self
+	}
+
+	/// Includes gadgets ending in a syscall or other interrupt
+	pub fn sys(mut self, sys: bool) -> Self {
+		self.sys = sys;
+		self
+	}
+
+	/// Includes gadgets ending in a controllable branch or call
+	pub fn jop(mut self, jop: bool) -> Self {
+		self.jop = jop;
+		self
+	}
+
+	/// Includes potentially low-quality gadgets such as prefixes, conditional branches, and near
+	/// branches
+	pub fn noisy(mut self, noisy: bool) -> Self {
+		self.noisy = noisy;
+		self
+	}
+
+	/// Only keeps the lowest address of gadgets with identical instructions
+	pub fn uniq(mut self, uniq: bool) -> Self {
+		self.uniq = uniq;
+		self
+	}
+
+	pub fn max_instructions(mut self, max_instructions: usize) -> Self {
+		assert!(max_instructions > 0);
+		self.max_instructions = max_instructions;
+		self
+	}
+
+	/// Only keeps gadgets with an address within the inclusive range `from..=to` - gadgets within
+	/// any of the given ranges are kept if this is called more than once
+	pub fn range(mut self, from: usize, to: usize) -> Self {
+		self.ranges.push((from, to));
+		self
+	}
+
+	/// Only searches sections with the given name eg. `.text` - sections with any of the given
+	/// names are searched if this is called more than once
+	pub fn section(mut self, name: &str) -> Self {
+		self.section_names.push(name.to_string());
+		self
+	}
+
+	/// Only keeps gadgets within the function with the given name - gadgets within any of the
+	/// given functions are kept if this is called more than once
+	pub fn function(mut self, name: &str) -> Self {
+		self.functions.push(name.to_string());
+		self
+	}
+
+	/// Excludes gadgets within the function with the given name
+	pub fn exclude_function(mut self, name: &str) -> Self {
+		self.excluded_functions.push(name.to_string());
+		self
+	}
+
+	/// Only keeps gadgets whose formatted instructions match every given regex
+	pub fn regex(mut self, regex: Regex) -> Self {
+		self.regices.push(regex);
+		self
+	}
+
+	/// Only keeps gadgets matching every given query
+	pub fn query(mut self, query: GadgetFilter) -> Self {
+		self.queries.push(query);
+		self
+	}
+
+	/// Only keeps gadgets which alter the stack pointer
+	pub fn stack_pivot(mut self, stack_pivot: bool) -> Self {
+		self.stack_pivot = stack_pivot;
+		self
+	}
+
+	/// Only keeps gadgets which alter the base pointer
+	pub fn base_pivot(mut self, base_pivot: bool) -> Self {
+		self.base_pivot = base_pivot;
+		self
+	}
+
+	/// Only keeps gadgets which behave like a function epilogue - see [`Gadget::is_epilogue`]
+	pub fn epilogue(mut self, epilogue: bool) -> Self {
+		self.epilogue = epilogue;
+		self
+	}
+
+	/// Only keeps gadgets which invoke `sigreturn` - see [`Gadget::is_sigreturn`]
+	pub fn sigreturn(mut self, sigreturn: bool) -> Self {
+		self.sigreturn = sigreturn;
+		self
+	}
+
+	/// Only keeps jump and call gadgets starting with an `endbr` instruction, which are the only
+	/// gadgets reachable through indirect branches when CET Indirect Branch Tracking is enforced -
+	/// gadgets reached through a `ret` are kept as IBT doesn't track returns
+	///
+	/// If this is `None`, it is enabled for binaries which opt into IBT through their GNU property
+	/// note - this is only known when searching a [`Binary`], rather than its sections alone.
+	pub fn ibt(mut self, ibt: Option<bool>) -> Self {
+		self.ibt = ibt;
+		self
+	}
+
+	/// Whether only jump and call gadgets starting with an `endbr` instruction are kept for a
+	/// binary - see [`GadgetFinder::ibt`]
+	pub fn ibt_enabled(&self, binary: &Binary) -> bool {
+		self.ibt.unwrap_or_else(|| {
+			binary
+				.mitigations()
+				.is_ok_and(|mitigations| mitigations.ibt == Some(true))
+		})
+	}
+
+	/// The number of bytes of a section disassembled at a time by [`GadgetFinder::stream`]
+	pub fn window_size(mut self, window_size: usize) -> Self {
+		assert!(window_size > 0);
+		self.window_size = window_size;
+		self
+	}
+
+	/// Excludes gadgets whose packed address contains any of the given bytes, and also those
+	/// whose instruction encoding does if `encoding` is set
+	pub fn bad_bytes(mut self, bad_bytes: &[u8], encoding: bool) -> Self {
+		self.bad_bytes = bad_bytes.to_vec();
+		self.bad_bytes_encoding = encoding;
+		self
+	}
+
+	/// Finds the executable sections of a binary according to the `raw`, `slice` and `section`
+	/// options
+	pub fn sections<'b>(&self, binary: &'b Binary) -> Result<Vec<Section<'b>>> {
+		let mut sections = binary.sections(self.raw, self.slice.as_deref())?;
+		sections.retain(|section| self.searches(section));
+		Ok(sections)
+	}
+
+	/// Whether a section is one of those selected by [`GadgetFinder::section`]
+	pub fn searches(&self, section: &Section) -> bool {
+		self.section_names.is_empty()
+			|| self
+				.section_names
+				.iter()
+				.any(|name| name == section.name())
+	}
+
+	/// Finds the gadgets within the executable sections of a binary
+	pub fn find(&self, binary: &Binary) -> Result<Vec<(Gadget, usize)>> {
+		let sections = self.sections(binary)?;
+		self.find_in(binary, &sections)
+	}
+
+	/// Finds the gadgets within sections which have already been read from a binary, using the
+	/// binary's index if a cache directory is set
+	pub fn find_in(&self, binary: &Binary, sections: &[Section]) -> Result<Vec<(Gadget, usize)>> {
+		let ibt = self.ibt_enabled(binary);
+		let cache_dir = match &self.cache_dir {
+			Some(cache_dir) => cache_dir,
+			None => return Ok(self.disassemble_and_find(sections, ibt)),
+		};
+		let path = GadgetIndex::path(cache_dir, binary, self.raw, self.slice.as_deref());
+		let index = match GadgetIndex::load(&path) {
+			Some(index) if index.covers(sections, self.max_instructions, self.noisy) => index,
+			_ => {
+				let index = GadgetIndex::build(sections, self.max_instructions, self.noisy);
+				index.save(&path)?;
+				index
+			}
+		};
+		let gadgets = sections.iter().enumerate().flat_map(|(n, section)| {
+			index
+				.gadgets(
+					n,
+					section,
+					self.max_instructions,
+					self.rop,
+					self.sys,
+					self.jop,
+					self.uniq,
+				)
+				.into_iter()
+				.filter(|(g, address)| !self.has_bad_bytes(section, g, *address))
+				.filter(|&(_, address)| self.in_range(address))
+				.filter(|&(_, address)| self.in_functions(section, address))
+		});
+		Ok(self.dedup_and_sort(gadgets, ibt))
+	}
+
+	/// Finds the gadgets within sections which have already been read from a binary
+	pub fn find_in_sections(&self, sections: &[Section]) -> Vec<(Gadget, usize)> {
+		self.disassemble_and_find(sections, self.ibt.unwrap_or(false))
+	}
+
+	fn disassemble_and_find(&self, sections: &[Section], ibt: bool) -> Vec<(Gadget, usize)> {
+		let gadgets = sections
+			.iter()
+			.filter_map(Disassembly::new)
+			.flat_map(|dis| self.gadgets_in(&dis));
+		self.dedup_and_sort(gadgets, ibt)
+	}
+
+	/// Removes duplicate gadgets and those which are filtered out, sorting the rest by address
+	fn dedup_and_sort(
+		&self,
+		gadgets: impl Iterator<Item = (Gadget, usize)>,
+		ibt: bool,
+	) -> Vec<(Gadget, usize)> {
+		let mut gadget_to_addr = HashMap::new();
+		for (gadget, address) in gadgets {
+			gadget_to_addr
+				.entry(gadget)
+				.and_modify(|lowest: &mut usize| *lowest = (*lowest).min(address))
+				.or_insert(address);
+		}
+
+		let mut gadgets = gadget_to_addr
+			.into_iter()
+			.filter(|(g, _)| self.keep(g, ibt))
+			.collect::<Vec<_>>();
+		gadgets.sort_unstable_by_key(|(_, addr)| *addr);
+		gadgets
+	}
+
+	/// Lazily finds the gadgets within sections, disassembling `window_size` bytes of a section at
+	/// a time so that memory use stays bounded for large binaries
+	///
+	/// Gadgets are sorted by address within each window, and only a hash of each gadget is kept
+	/// to remove duplicates.
+	pub fn stream<'s>(&'s self, sections: &'s [Section<'s>]) -> GadgetStream<'s> {
+		GadgetStream {
+			finder: self,
+			sections,
+			section_index: 0,
+			window_start: 0,
+			pending: Vec::new().into_iter(),
+			seen: HashSet::new(),
+		}
+	}
+
+	/// Finds the gadgets with tails in a disassembled section or window of one, before
+	/// deduplication
+	fn gadgets_in(&self, dis: &Disassembly) -> Vec<(Gadget, usize)> {
+		dis.tails()
+			.into_par_iter()
+			.filter(|offset| dis.is_tail_at(*offset, self.rop, self.sys, self.jop, self.noisy))
+			.flat_map_iter(|tail| {
+				dis.gadgets_from_tail(tail, self.max_instructions, self.noisy, self.uniq)
+			})
+			.filter(|(g, address)| !self.has_bad_bytes(dis.section(), g, *address))
+			.filter(|&(_, address)| self.in_range(address))
+			.filter(|&(_, address)| self.in_functions(dis.section(), address))
+			.collect()
+	}
+
+	/// Whether a gadget passes the regex, query, pivot, sigreturn and IBT filters
+	fn keep(&self, gadget: &Gadget, ibt: bool) -> bool {
+		let regex_match = || {
+			let mut formatted = String::new();
+			gadget.format_instruction(&mut formatted);
+			self.regices.iter().all(|r| r.is_match(&formatted))
+		};
+		(self.regices.is_empty() || regex_match())
+			&& self.queries.iter().all(|q| q.matches(gadget))
+			&& (!self.stack_pivot | gadget.is_stack_pivot())
+			&& (!self.base_pivot | gadget.is_base_pivot())
+			&& (!self.epilogue | gadget.is_epilogue())
+			&& (!self.sigreturn | gadget.is_sigreturn())
+			&& (!ibt | (gadget.tail_kind() != Some(TailKind::Jop)) | gadget.is_branch_target())
+	}
+
+	fn in_range(&self, address: usize) -> bool {
+		self.ranges.is_empty()
+			|| self
+				.ranges
+				.iter()
+				.any(|(from, to)| *from <= address && address <= *to)
+	}
+
+	/// Whether a gadget is within one of the selected functions and none of the excluded ones
+	fn in_functions(&self, section: &Section, address: usize) -> bool {
+		if self.functions.is_empty() && self.excluded_functions.is_empty() {
+			return true;
+		}
+		let name = section.symbol(address).map(|(symbol, _)| symbol.name());
+		let listed = |functions: &[String]| {
+			name.is_some_and(|name| functions.iter().any(|function| function == name))
+		};
+		(self.functions.is_empty() || listed(&self.functions)) && !listed(&self.excluded_functions)
+	}
+
+	/// Whether the packed address of a gadget, or optionally its encoding, contains a bad byte
+	fn has_bad_bytes(&self, section: &Section, gadget: &Gadget, address: usize) -> bool {
+		if self.bad_bytes.is_empty() {
+			return false;
+		}
+		let in_address = section
+			.pack_address(address)
+			.iter()
+			.any(|b| self.bad_bytes.contains(b));
+		let in_encoding = || {
+			section
+				.bytes_at(address, gadget.byte_len())
+				.is_some_and(|bytes| bytes.iter().any(|b| self.bad_bytes.contains(b)))
+		};
+		in_address || (self.bad_bytes_encoding && in_encoding())
+	}
+}
+
+/// Iterator over the gadgets of a binary - see [`GadgetFinder::stream`]
+pub struct GadgetStream<'s> {
+	finder: &'s GadgetFinder,
+	sections: &'s [Section<'s>],
+	section_index: usize,
+	window_start: usize,
+	pending: vec::IntoIter<(Gadget, usize)>,
+	seen: HashSet<u64>,
+}
+
+impl Iterator for GadgetStream<'_> {
+	type Item = (Gadget, usize);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			if let Some(gadget) = self.pending.next() {
+				return Some(gadget);
+			}
+
+			let section = self.sections.get(self.section_index)?;
+			let tails = self.window_start..self.window_start + self.finder.window_size;
+			self.window_start = tails.end;
+			if tails.start >= section.bytes().len() {
+				self.section_index += 1;
+				self.window_start = 0;
+				continue;
+			}
+
+			let lookback = Disassembly::lookback(self.finder.max_instructions);
+			let dis = match Disassembly::window(section, tails, lookback) {
+				Some(dis) => dis,
+				None => continue,
+			};
+			let mut gadgets = self.finder.gadgets_in(&dis);
+			gadgets.sort_unstable_by_key(|(_, addr)| *addr);
+			let ibt = self.finder.ibt.unwrap_or(false);
+			gadgets.retain(|(g, _)| self.finder.keep(g, ibt) && self.seen.insert(hash(g)));
+			self.pending = gadgets.into_iter();
+		}
+	}
+}
+
+fn hash(gadget: &Gadget) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	gadget.hash(&mut hasher);
+	hasher.finish()
+}
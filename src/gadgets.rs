@@ -1,54 +1,279 @@
-use crate::rules::{
-	is_base_pivot_head, is_rop_gadget_head, is_stack_pivot_head, is_stack_pivot_tail,
+use crate::{
+	binary::Arch,
+	effects::Effects,
+	generic::GenericInstruction,
+	rules::{
+		is_base_pivot_head, is_branch_target, is_rop_gadget_head, is_shadow_stack_checked,
+		is_sigreturn, is_stack_pivot_head, is_stack_pivot_tail, tail_kind, TailKind,
+	},
 };
-use iced_x86::{Formatter, FormatterOutput, FormatterTextKind, Instruction};
-use std::hash::Hash;
+use iced_x86::{Formatter, FormatterOutput, FormatterTextKind, Instruction, IntelFormatter};
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Eq, Hash, PartialEq)]
+enum Body {
+	X86(Vec<Instruction>),
+	Generic(Vec<GenericInstruction>),
+}
+
+/// Gadgets are compared and hashed by their instructions and `unique_id` alone
+#[derive(Debug)]
 pub struct Gadget {
-	instructions: Vec<Instruction>,
+	body: Body,
 	unique_id: usize,
+	/// Effects loaded from an index, which are otherwise computed when asked for
+	effects: Option<Effects>,
+}
+
+impl PartialEq for Gadget {
+	fn eq(&self, other: &Self) -> bool {
+		self.body == other.body && self.unique_id == other.unique_id
+	}
+}
+
+impl Eq for Gadget {}
+
+impl Hash for Gadget {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.body.hash(state);
+		self.unique_id.hash(state);
+	}
 }
 
 impl Gadget {
-	pub fn instructions(&self) -> &[Instruction] { &self.instructions }
+	/// `unique_id` should be `0` unless identical gadgets at different addresses must be kept
+	/// apart, in which case it is the gadget's address
+	pub(crate) fn x86(instructions: Vec<Instruction>, unique_id: usize) -> Self {
+		Self {
+			body: Body::X86(instructions),
+			unique_id,
+			effects: None,
+		}
+	}
+
+	pub(crate) fn generic(instructions: Vec<GenericInstruction>, unique_id: usize) -> Self {
+		Self {
+			body: Body::Generic(instructions),
+			unique_id,
+			effects: None,
+		}
+	}
+
+	/// Attaches effects which were computed earlier, such as those saved in an index
+	pub(crate) fn with_effects(mut self, effects: Effects) -> Self {
+		self.effects = Some(effects);
+		self
+	}
+
+	/// The x86 instructions making up the gadget - empty for gadgets of other architectures
+	pub fn instructions(&self) -> &[Instruction] {
+		match &self.body {
+			Body::X86(instructions) => instructions,
+			Body::Generic(_) => &[],
+		}
+	}
+
+	/// The instructions making up a gadget of an architecture other than x86
+	pub fn generic_instructions(&self) -> &[GenericInstruction] {
+		match &self.body {
+			Body::X86(_) => &[],
+			Body::Generic(instructions) => instructions,
+		}
+	}
+
+	pub fn arch(&self) -> Arch {
+		match &self.body {
+			Body::X86(_) => Arch::X86,
+			Body::Generic(instructions) => instructions
+				.first()
+				.map(GenericInstruction::arch)
+				.unwrap_or(Arch::X86),
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		match &self.body {
+			Body::X86(instructions) => instructions.len(),
+			Body::Generic(instructions) => instructions.len(),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	/// The number of instructions counted towards the maximum length of a gadget, which leaves
+	/// out any delay slot following the tail
+	pub fn counted_len(&self) -> usize {
+		let delay_slot = self
+			.generic_instructions()
+			.iter()
+			.rev()
+			.nth(1)
+			.is_some_and(GenericInstruction::has_delay_slot);
+		self.len() - delay_slot as usize
+	}
 
 	pub fn is_stack_pivot(&self) -> bool {
-		match self.instructions.as_slice() {
-			[] => false,
-			[t] => is_stack_pivot_tail(t),
-			[h @ .., _] => h.iter().any(is_stack_pivot_head),
+		match &self.body {
+			Body::X86(instructions) => match instructions.as_slice() {
+				[] => false,
+				[t] => is_stack_pivot_tail(t),
+				[h @ .., _] => h.iter().any(is_stack_pivot_head),
+			},
+			// Tails such as `pop {r4, sp, pc}` may also move the stack pointer
+			Body::Generic(instructions) => instructions
+				.iter()
+				.any(GenericInstruction::is_stack_pivot_head),
 		}
 	}
 
 	pub fn is_base_pivot(&self) -> bool {
-		match self.instructions.as_slice() {
-			[] | [_] => false,
-			[h @ .., _] => h.iter().any(is_base_pivot_head),
+		match &self.body {
+			Body::X86(instructions) => match instructions.as_slice() {
+				[] | [_] => false,
+				[h @ .., _] => h.iter().any(is_base_pivot_head),
+			},
+			Body::Generic(instructions) => instructions
+				.iter()
+				.any(GenericInstruction::is_base_pivot_head),
+		}
+	}
+
+	/// Whether the gadget reloads the return address from the stack and moves the stack pointer
+	/// past it like a function epilogue, so that the gadget can be chained through its `ret`
+	///
+	/// This only applies to RISC-V and MIPS, where returning jumps to the return address register
+	/// without popping from the stack.
+	pub fn is_epilogue(&self) -> bool {
+		let instructions = self.generic_instructions();
+		instructions
+			.iter()
+			.any(GenericInstruction::is_return_address_restore)
+			&& instructions
+				.iter()
+				.any(GenericInstruction::is_stack_pivot_head)
+	}
+
+	/// Whether the gadget starts with an `endbr` instruction, so that indirect branches may land
+	/// on it when CET Indirect Branch Tracking is enforced
+	///
+	/// Only x86 gadgets can start with one.
+	pub fn is_branch_target(&self) -> bool {
+		self.instructions().first().is_some_and(is_branch_target)
+	}
+
+	/// Whether the gadget returns through an address which a CET shadow stack wouldn't hold, as
+	/// every gadget chained through a `ret` does
+	pub fn violates_shadow_stack(&self) -> bool {
+		self.instructions().iter().any(is_shadow_stack_checked)
+	}
+
+	/// Whether the gadget loads the number of `sigreturn` into `eax` and makes the syscall, such as
+	/// `mov eax, 0xf; syscall` on x86-64 or `mov eax, 0x77; int 0x80` on i386 - see
+	/// [`SigreturnFrame`](crate::srop::SigreturnFrame)
+	pub fn is_sigreturn(&self) -> bool { is_sigreturn(self.instructions()) }
+
+	/// Computes which registers, memory and flags the gadget depends on and modifies
+	///
+	/// Effects can only be computed for x86 gadgets - other architectures report an unknown
+	/// stack delta and no other effects.
+	pub fn effects(&self) -> Effects {
+		if let Some(effects) = &self.effects {
+			return effects.clone();
+		}
+		match &self.body {
+			Body::X86(instructions) => Effects::new(instructions),
+			Body::Generic(_) => Effects {
+				stack_delta: None,
+				..Effects::default()
+			},
+		}
+	}
+
+	pub fn tail_kind(&self) -> Option<TailKind> {
+		match &self.body {
+			Body::X86(instructions) => instructions.last().map(tail_kind),
+			Body::Generic(instructions) => instructions
+				.iter()
+				.rev()
+				.find_map(GenericInstruction::tail_kind),
+		}
+	}
+
+	/// Total length of the encoded gadget in bytes
+	pub fn byte_len(&self) -> usize {
+		match &self.body {
+			Body::X86(instructions) => instructions.iter().map(Instruction::len).sum(),
+			Body::Generic(instructions) => instructions.iter().map(GenericInstruction::len).sum(),
+		}
+	}
+
+	/// Formats each instruction separately as a `(mnemonic, operands)` pair
+	pub fn format_parts(&self) -> Vec<(String, String)> {
+		match &self.body {
+			Body::X86(instructions) => {
+				let mut formatter = formatter();
+				instructions
+					.iter()
+					.map(|i| {
+						let mut mnemonic = String::new();
+						let mut operands = String::new();
+						formatter.format_mnemonic(i, &mut mnemonic);
+						formatter.format_all_operands(i, &mut operands);
+						(mnemonic, operands)
+					})
+					.collect()
+			}
+			Body::Generic(instructions) => instructions
+				.iter()
+				.map(|i| (i.mnemonic().to_string(), i.operands().to_string()))
+				.collect(),
 		}
 	}
 
 	pub fn format_instruction(&self, output: &mut impl FormatterOutput) {
-		let mut formatter = iced_x86::IntelFormatter::new();
-		let options = iced_x86::Formatter::options_mut(&mut formatter);
-		options.set_hex_prefix("0x");
-		options.set_hex_suffix("");
-		options.set_space_after_operand_separator(true);
-		options.set_branch_leading_zeroes(false);
-		options.set_uppercase_hex(false);
-		options.set_rip_relative_addresses(true);
-		// Write instructions
-		let mut instructions = self.instructions.iter().peekable();
-		while let Some(i) = instructions.next() {
-			formatter.format(i, output);
-			output.write(";", FormatterTextKind::Text);
-			if instructions.peek().is_some() {
-				output.write(" ", FormatterTextKind::Text);
+		match &self.body {
+			Body::X86(instructions) => {
+				let mut formatter = formatter();
+				// Write instructions
+				let mut instructions = instructions.iter().peekable();
+				while let Some(i) = instructions.next() {
+					formatter.format(i, output);
+					output.write(";", FormatterTextKind::Text);
+					if instructions.peek().is_some() {
+						output.write(" ", FormatterTextKind::Text);
+					}
+				}
+			}
+			Body::Generic(instructions) => {
+				let mut instructions = instructions.iter().peekable();
+				while let Some(i) = instructions.next() {
+					output.write(i.mnemonic(), FormatterTextKind::Mnemonic);
+					if !i.operands().is_empty() {
+						output.write(" ", FormatterTextKind::Text);
+						output.write(i.operands(), FormatterTextKind::Text);
+					}
+					output.write(";", FormatterTextKind::Text);
+					if instructions.peek().is_some() {
+						output.write(" ", FormatterTextKind::Text);
+					}
+				}
 			}
 		}
 	}
 }
 
+pub(crate) fn formatter() -> IntelFormatter {
+	let mut formatter = IntelFormatter::new();
+	let options = formatter.options_mut();
+	options.set_hex_prefix("0x");
+	options.set_hex_suffix("");
+	options.set_space_after_operand_separator(true);
+	options.set_branch_leading_zeroes(false);
+	options.set_uppercase_hex(false);
+	options.set_rip_relative_addresses(true);
+	formatter
+}
+
 pub struct GadgetIterator<'d> {
 	section_start: usize,
 	tail_instruction: Instruction,
@@ -120,10 +345,7 @@ impl Iterator for GadgetIterator<'_> {
 					self.section_start + current_start_index
 				};
 				return Some((
-					Gadget {
-						instructions,
-						unique_id,
-					},
+					Gadget::x86(instructions, unique_id),
 					self.section_start + current_start_index,
 				));
 			}
@@ -140,10 +362,7 @@ impl Iterator for GadgetIterator<'_> {
 				self.section_start + self.start_index
 			};
 			return Some((
-				Gadget {
-					instructions,
-					unique_id,
-				},
+				Gadget::x86(instructions, unique_id),
 				self.section_start + self.start_index,
 			));
 		}
@@ -151,3 +370,123 @@ impl Iterator for GadgetIterator<'_> {
 		None
 	}
 }
+
+pub struct GenericGadgetIterator<'d> {
+	section_start: usize,
+	alignment: usize,
+	tail_instruction: GenericInstruction,
+	delay_slot: Option<GenericInstruction>,
+	predecessors: &'d [Option<GenericInstruction>],
+	max_instructions: usize,
+	noisy: bool,
+	uniq: bool,
+	start_slot: usize,
+	finished: bool,
+}
+
+impl<'d> GenericGadgetIterator<'d> {
+	/// `predecessors` holds the instruction decoded at each multiple of `alignment` bytes before
+	/// the tail, with `start_slot` giving the position of the first of these within the section.
+	/// `delay_slot` is the instruction following a delayed branch tail, which is appended to every
+	/// gadget without counting towards `max_instructions`.
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		section_start: usize,
+		alignment: usize,
+		tail_instruction: GenericInstruction,
+		delay_slot: Option<GenericInstruction>,
+		predecessors: &'d [Option<GenericInstruction>],
+		max_instructions: usize,
+		noisy: bool,
+		uniq: bool,
+		start_slot: usize,
+	) -> Self {
+		Self {
+			section_start,
+			alignment,
+			tail_instruction,
+			delay_slot,
+			predecessors,
+			max_instructions,
+			noisy,
+			uniq,
+			start_slot,
+			finished: false,
+		}
+	}
+
+	fn gadget(&self, mut instructions: Vec<GenericInstruction>, slot: usize) -> (Gadget, usize) {
+		instructions.extend(self.delay_slot.clone());
+		let address = self.section_start + slot * self.alignment;
+		// Branching to a Thumb gadget requires the low bit set to switch instruction sets
+		let address = match self.tail_instruction.arch() {
+			Arch::Thumb => address | 1,
+			_ => address,
+		};
+		let unique_id = if self.uniq { 0 } else { address };
+		(
+			Gadget::generic(instructions, unique_id),
+			address,
+		)
+	}
+}
+
+impl Iterator for GenericGadgetIterator<'_> {
+	type Item = (Gadget, usize);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let mut instructions = Vec::new();
+
+		'outer: while !self.predecessors.is_empty() {
+			instructions.clear();
+			let len = self.predecessors.len();
+			let mut index = 0;
+			while index < len && instructions.len() < self.max_instructions - 1 {
+				let instruction = match &self.predecessors[index] {
+					Some(i) if i.is_rop_gadget_head(self.noisy) => i,
+					_ => {
+						// Found a bad
+						self.predecessors = &self.predecessors[1..];
+						self.start_slot += 1;
+						continue 'outer;
+					}
+				};
+				instructions.push(instruction.clone());
+				index += instruction.len() / self.alignment;
+			}
+
+			let current_start_slot = self.start_slot;
+
+			self.predecessors = &self.predecessors[1..];
+			self.start_slot += 1;
+
+			if index == len {
+				instructions.push(self.tail_instruction.clone());
+				return Some(self.gadget(instructions, current_start_slot));
+			}
+		}
+
+		if !self.finished {
+			self.finished = true;
+			return Some(self.gadget(vec![self.tail_instruction.clone()], self.start_slot));
+		}
+
+		None
+	}
+}
+
+pub enum Gadgets<'d> {
+	X86(GadgetIterator<'d>),
+	Generic(GenericGadgetIterator<'d>),
+}
+
+impl Iterator for Gadgets<'_> {
+	type Item = (Gadget, usize);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		match self {
+			Gadgets::X86(gadgets) => gadgets.next(),
+			Gadgets::Generic(gadgets) => gadgets.next(),
+		}
+	}
+}
@@ -0,0 +1,215 @@
+use crate::{
+	binary::{Arch, Bitness, Endian},
+	rules::{aarch64, arm, mips, powerpc, riscv, TailKind},
+};
+use capstone::{arch, prelude::*, Capstone};
+use std::hash::{Hash, Hasher};
+
+/// Longest encoding of a single instruction on any of the generic architectures
+pub const MAX_GENERIC_INSTRUCTION_LENGTH: usize = 4;
+
+/// An instruction decoded with capstone, for architectures which iced-x86 does not cover
+///
+/// As with iced-x86 instructions, equality and hashing ignore the address so that identical
+/// gadgets found at different locations compare equal.
+#[derive(Debug, Clone)]
+pub struct GenericInstruction {
+	arch: Arch,
+	address: u64,
+	len: u8,
+	bytes: [u8; MAX_GENERIC_INSTRUCTION_LENGTH],
+	mnemonic: String,
+	operands: String,
+}
+
+impl PartialEq for GenericInstruction {
+	fn eq(&self, other: &Self) -> bool {
+		self.arch == other.arch
+			&& self.bytes() == other.bytes()
+			&& self.mnemonic == other.mnemonic
+			&& self.operands == other.operands
+	}
+}
+
+impl Eq for GenericInstruction {}
+
+impl Hash for GenericInstruction {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.arch.hash(state);
+		self.bytes().hash(state);
+		self.mnemonic.hash(state);
+		self.operands.hash(state);
+	}
+}
+
+impl GenericInstruction {
+	pub fn arch(&self) -> Arch { self.arch }
+
+	pub fn address(&self) -> u64 { self.address }
+
+	pub fn len(&self) -> usize { self.len as usize }
+
+	pub fn is_empty(&self) -> bool { self.len == 0 }
+
+	pub fn bytes(&self) -> &[u8] { &self.bytes[..self.len()] }
+
+	pub fn mnemonic(&self) -> &str { &self.mnemonic }
+
+	pub fn operands(&self) -> &str { &self.operands }
+
+	/// The destination register of instructions which write to their first operand
+	pub fn first_operand(&self) -> &str {
+		self.operands.split(',').next().unwrap_or_default().trim()
+	}
+
+	pub fn tail_kind(&self) -> Option<TailKind> {
+		match self.arch {
+			Arch::AArch64 => aarch64::tail_kind(self),
+			Arch::Arm | Arch::Thumb => arm::tail_kind(self),
+			Arch::RiscV { .. } => riscv::tail_kind(self),
+			Arch::Mips => mips::tail_kind(self),
+			Arch::PowerPC => powerpc::tail_kind(self),
+			Arch::X86 => None,
+		}
+	}
+
+	pub fn is_gadget_tail(&self, rop: bool, sys: bool, jop: bool) -> bool {
+		match self.tail_kind() {
+			Some(TailKind::Ret) => rop,
+			Some(TailKind::Syscall) => sys,
+			Some(TailKind::Jop) => jop,
+			None => false,
+		}
+	}
+
+	pub fn is_rop_gadget_head(&self, noisy: bool) -> bool {
+		match self.arch {
+			Arch::AArch64 => aarch64::is_rop_gadget_head(self, noisy),
+			Arch::Arm | Arch::Thumb => arm::is_rop_gadget_head(self, noisy),
+			Arch::RiscV { .. } => riscv::is_rop_gadget_head(self, noisy),
+			Arch::Mips => mips::is_rop_gadget_head(self, noisy),
+			Arch::PowerPC => powerpc::is_rop_gadget_head(self, noisy),
+			Arch::X86 => false,
+		}
+	}
+
+	pub fn is_stack_pivot_head(&self) -> bool {
+		match self.arch {
+			Arch::AArch64 => aarch64::is_stack_pivot_head(self),
+			Arch::Arm | Arch::Thumb => arm::is_stack_pivot_head(self),
+			Arch::RiscV { .. } => riscv::is_stack_pivot_head(self),
+			Arch::Mips => mips::is_stack_pivot_head(self),
+			Arch::PowerPC => powerpc::is_stack_pivot_head(self),
+			Arch::X86 => false,
+		}
+	}
+
+	/// Whether this reloads the return address register from the stack - only tracked for
+	/// architectures where returning doesn't pop the return address itself
+	pub fn is_return_address_restore(&self) -> bool {
+		match self.arch {
+			Arch::RiscV { .. } => riscv::is_return_address_restore(self),
+			Arch::Mips => mips::is_return_address_restore(self),
+			_ => false,
+		}
+	}
+
+	/// Whether the instruction following this one executes before control is transferred
+	pub fn has_delay_slot(&self) -> bool {
+		match self.arch {
+			Arch::Mips => mips::has_delay_slot(self),
+			_ => false,
+		}
+	}
+
+	pub fn is_base_pivot_head(&self) -> bool {
+		match self.arch {
+			Arch::AArch64 => aarch64::is_base_pivot_head(self),
+			Arch::Arm | Arch::Thumb => arm::is_base_pivot_head(self),
+			Arch::RiscV { .. } => riscv::is_base_pivot_head(self),
+			Arch::Mips => mips::is_base_pivot_head(self),
+			Arch::PowerPC => powerpc::is_base_pivot_head(self),
+			Arch::X86 => false,
+		}
+	}
+}
+
+/// Wraps a capstone handle configured for a single architecture and mode
+pub struct GenericDisassembler {
+	arch: Arch,
+	capstone: Capstone,
+}
+
+impl GenericDisassembler {
+	pub fn new(arch: Arch, bitness: Bitness, endian: Endian) -> Option<Self> {
+		let endian = match endian {
+			Endian::Little => capstone::Endian::Little,
+			Endian::Big => capstone::Endian::Big,
+		};
+		let capstone = match arch {
+			Arch::AArch64 => Capstone::new()
+				.arm64()
+				.mode(arch::arm64::ArchMode::Arm)
+				.build(),
+			Arch::Arm => Capstone::new().arm().mode(arch::arm::ArchMode::Arm).build(),
+			Arch::Thumb => Capstone::new()
+				.arm()
+				.mode(arch::arm::ArchMode::Thumb)
+				.build(),
+			Arch::RiscV { compressed } => {
+				let mode = match bitness {
+					Bitness::Bits64 => arch::riscv::ArchMode::RiscV64,
+					Bitness::Bits32 => arch::riscv::ArchMode::RiscV32,
+				};
+				let extra = compressed.then_some(arch::riscv::ArchExtraMode::RiscVC);
+				Capstone::new()
+					.riscv()
+					.mode(mode)
+					.extra_mode(extra.into_iter())
+					.build()
+			}
+			Arch::Mips => {
+				let mode = match bitness {
+					Bitness::Bits64 => arch::mips::ArchMode::Mips64,
+					Bitness::Bits32 => arch::mips::ArchMode::Mips32,
+				};
+				Capstone::new().mips().mode(mode).endian(endian).build()
+			}
+			Arch::PowerPC => {
+				let mode = match bitness {
+					Bitness::Bits64 => arch::ppc::ArchMode::Mode64,
+					Bitness::Bits32 => arch::ppc::ArchMode::Mode32,
+				};
+				Capstone::new().ppc().mode(mode).endian(endian).build()
+			}
+			Arch::X86 => return None,
+		}
+		.ok()?;
+		Some(Self { arch, capstone })
+	}
+
+	/// Instructions may only begin at offsets which are a multiple of this
+	pub fn alignment(&self) -> usize {
+		match self.arch {
+			Arch::Thumb | Arch::RiscV { compressed: true } => 2,
+			Arch::RiscV { compressed: false } => 4,
+			Arch::AArch64 | Arch::Arm | Arch::Mips | Arch::PowerPC | Arch::X86 => 4,
+		}
+	}
+
+	pub fn decode(&self, bytes: &[u8], address: u64) -> Option<GenericInstruction> {
+		let end = bytes.len().min(MAX_GENERIC_INSTRUCTION_LENGTH);
+		let decoded = self.capstone.disasm_count(&bytes[..end], address, 1).ok()?;
+		let instr = decoded.iter().next()?;
+		let mut encoding = [0; MAX_GENERIC_INSTRUCTION_LENGTH];
+		encoding[..instr.len()].copy_from_slice(instr.bytes());
+		Some(GenericInstruction {
+			arch: self.arch,
+			address,
+			len: instr.len() as u8,
+			bytes: encoding,
+			mnemonic: instr.mnemonic()?.to_string(),
+			operands: instr.op_str().unwrap_or_default().to_string(),
+		})
+	}
+}
@@ -0,0 +1,270 @@
+use crate::{
+	binary::{Arch, Binary, Section},
+	disassembler::{Disassembler, Disassembly},
+	effects::{register_name, Effects},
+	error::Result,
+	gadgets::Gadget,
+	generic::GenericDisassembler,
+	output::hex,
+	rules::TailKind,
+};
+use iced_x86::{Instruction, Register};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::HashMap,
+	fs::{create_dir_all, read, write},
+	path::{Path, PathBuf},
+};
+
+/// Bumped whenever the layout of an index or the gadgets found for the same options change, so
+/// that stale indexes are rebuilt
+const INDEX_VERSION: u32 = 2;
+
+/// Every gadget found in the sections of a binary, saved to disk so that later searches of the
+/// same binary can skip disassembling it
+///
+/// Gadgets are found with every kind of tail and without deduplication, so that any search with
+/// at most `max_instructions` instructions and the same `noisy` setting can be answered from the
+/// index. Locations are stored relative to their section so that the index stays valid when the
+/// binary is rebased, and the effects of each gadget are stored so that they needn't be
+/// recomputed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GadgetIndex {
+	version: u32,
+	max_instructions: usize,
+	noisy: bool,
+	sections: Vec<IndexedSection>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedSection {
+	name: String,
+	gadgets: Vec<IndexedGadget>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedGadget {
+	/// Offset of the gadget from the start of its section, which includes the low bit of Thumb
+	/// gadgets
+	offset: usize,
+	bytes: String,
+	tail: Option<TailKind>,
+	/// Number of instructions counted towards the maximum - see [`Gadget::counted_len`]
+	len: usize,
+	effects: IndexedEffects,
+}
+
+/// [`Effects`] with registers stored by name
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedEffects {
+	reads: Vec<String>,
+	writes: Vec<String>,
+	clobbers: Vec<String>,
+	stack_delta: Option<i64>,
+	memory_read: bool,
+	memory_write: bool,
+	flags_modified: bool,
+}
+
+impl IndexedEffects {
+	fn new(effects: Effects) -> Self {
+		let names = |registers: Vec<Register>| registers.into_iter().map(register_name).collect();
+		Self {
+			reads: names(effects.reads),
+			writes: names(effects.writes),
+			clobbers: names(effects.clobbers),
+			stack_delta: effects.stack_delta,
+			memory_read: effects.memory_read,
+			memory_write: effects.memory_write,
+			flags_modified: effects.flags_modified,
+		}
+	}
+
+	/// Looks registers up by name in `registers`, returning `None` if any are unknown
+	fn effects(&self, registers: &HashMap<String, Register>) -> Option<Effects> {
+		let lookup = |names: &[String]| {
+			names
+				.iter()
+				.map(|name| registers.get(name).copied())
+				.collect::<Option<Vec<_>>>()
+		};
+		Some(Effects {
+			reads: lookup(&self.reads)?,
+			writes: lookup(&self.writes)?,
+			clobbers: lookup(&self.clobbers)?,
+			stack_delta: self.stack_delta,
+			memory_read: self.memory_read,
+			memory_write: self.memory_write,
+			flags_modified: self.flags_modified,
+		})
+	}
+}
+
+impl GadgetIndex {
+	pub fn build(sections: &[Section], max_instructions: usize, noisy: bool) -> Self {
+		let sections = sections
+			.iter()
+			.map(|section| {
+				let section_start = section.program_base() + section.section_vaddr();
+				let gadgets = match Disassembly::new(section) {
+					Some(dis) => dis
+						.tails()
+						.into_par_iter()
+						.filter(|offset| dis.is_tail_at(*offset, true, true, true, noisy))
+						.flat_map_iter(|tail| {
+							dis.gadgets_from_tail(tail, max_instructions, noisy, false)
+						})
+						.map(|(gadget, address)| IndexedGadget {
+							offset: address - section_start,
+							bytes: section
+								.bytes_at(address, gadget.byte_len())
+								.map(hex)
+								.unwrap_or_default(),
+							tail: gadget.tail_kind(),
+							len: gadget.counted_len(),
+							effects: IndexedEffects::new(gadget.effects()),
+						})
+						.collect(),
+					None => Vec::new(),
+				};
+				IndexedSection {
+					name: section.name().to_string(),
+					gadgets,
+				}
+			})
+			.collect();
+		Self {
+			version: INDEX_VERSION,
+			max_instructions,
+			noisy,
+			sections,
+		}
+	}
+
+	/// Reads an index, returning `None` if there is none or it is unreadable
+	pub fn load(path: impl AsRef<Path>) -> Option<Self> {
+		let bytes = read(path).ok()?;
+		let index = serde_json::from_slice::<Self>(&bytes).ok()?;
+		(index.version == INDEX_VERSION).then_some(index)
+	}
+
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+		let path = path.as_ref();
+		if let Some(dir) = path.parent() {
+			create_dir_all(dir)?;
+		}
+		write(path, serde_json::to_vec(self)?)?;
+		Ok(())
+	}
+
+	/// Where the index of a binary is kept within `dir`, named after a hash of the binary's
+	/// contents and the options used to find its sections
+	pub fn path(
+		dir: impl AsRef<Path>,
+		binary: &Binary,
+		raw: Option<bool>,
+		slice: Option<&str>,
+	) -> PathBuf {
+		let mut hasher = Sha256::new();
+		hasher.update(binary.bytes());
+		hasher.update(format!("{:?}{:?}{:?}", raw, slice, binary.section_headers()));
+		dir.as_ref().join(format!("{}.json", hex(&hasher.finalize())))
+	}
+
+	/// Whether the index holds every gadget a search with these options could find in `sections`
+	pub fn covers(&self, sections: &[Section], max_instructions: usize, noisy: bool) -> bool {
+		self.max_instructions >= max_instructions
+			&& self.noisy == noisy
+			&& self.sections.len() == sections.len()
+			&& self
+				.sections
+				.iter()
+				.zip(sections)
+				.all(|(indexed, section)| indexed.name == section.name())
+	}
+
+	/// Decodes the indexed gadgets of one of the sections the index was built from, keeping those
+	/// of at most `max_instructions` instructions with one of the requested tails
+	#[allow(clippy::too_many_arguments)]
+	pub fn gadgets(
+		&self,
+		section_index: usize,
+		section: &Section,
+		max_instructions: usize,
+		rop: bool,
+		sys: bool,
+		jop: bool,
+		uniq: bool,
+	) -> Vec<(Gadget, usize)> {
+		let indexed = match self.sections.get(section_index) {
+			Some(indexed) => indexed,
+			None => return Vec::new(),
+		};
+		let section_start = section.program_base() + section.section_vaddr();
+		let generic = GenericDisassembler::new(section.arch(), section.bitness(), section.endian());
+		let registers = Register::values()
+			.map(|register| (register_name(register), register))
+			.collect::<HashMap<_, _>>();
+		indexed
+			.gadgets
+			.iter()
+			.filter(|g| g.len <= max_instructions)
+			.filter(|g| match g.tail {
+				Some(TailKind::Ret) => rop,
+				Some(TailKind::Syscall) => sys,
+				Some(TailKind::Jop) => jop,
+				None => false,
+			})
+			.filter_map(|g| {
+				let address = section_start + g.offset;
+				let bytes = unhex(&g.bytes)?;
+				let unique_id = if uniq { 0 } else { address };
+				let gadget = match section.arch() {
+					Arch::X86 => {
+						let mut disassembler = Disassembler::new(section.bitness(), &bytes);
+						let mut instructions = Vec::new();
+						let mut offset = 0;
+						while offset < bytes.len() {
+							let mut instruction = Instruction::default();
+							let ip = (address + offset) as u64;
+							disassembler.decode_at_offset(ip, offset, &mut instruction);
+							if instruction.is_invalid() {
+								return None;
+							}
+							offset += instruction.len();
+							instructions.push(instruction);
+						}
+						Gadget::x86(instructions, unique_id)
+					}
+					_ => {
+						let generic = generic.as_ref()?;
+						// Thumb addresses have the low bit set for interworking
+						let start = match section.arch() {
+							Arch::Thumb => address & !1,
+							_ => address,
+						};
+						let mut instructions = Vec::new();
+						let mut offset = 0;
+						while offset < bytes.len() {
+							let instruction =
+								generic.decode(&bytes[offset..], (start + offset) as u64)?;
+							offset += instruction.len();
+							instructions.push(instruction);
+						}
+						Gadget::generic(instructions, unique_id)
+					}
+				};
+				Some((gadget.with_effects(g.effects.effects(&registers)?), address))
+			})
+			.collect()
+	}
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+	(0..s.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+		.collect()
+}
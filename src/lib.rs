@@ -1,6 +1,19 @@
 pub mod binary;
+pub mod chain;
+pub mod diff;
 pub mod disassembler;
+pub mod effects;
 pub mod error;
+pub mod finder;
 pub mod formatter;
 pub mod gadgets;
+pub mod generic;
+pub mod index;
+pub mod mitigations;
+pub mod output;
+pub mod patterns;
+pub mod process;
+pub mod query;
 pub mod rules;
+pub mod srop;
+pub mod symbols;
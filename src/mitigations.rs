@@ -0,0 +1,196 @@
+use crate::binary::elf_notes;
+use goblin::{
+	elf::{
+		dynamic::{DF_1_NOW, DF_BIND_NOW, DT_BIND_NOW},
+		header::{EM_386, EM_X86_64},
+		program_header::{PF_X, PT_GNU_RELRO, PT_GNU_STACK},
+		Elf,
+	},
+	mach::{header::MH_ALLOW_STACK_EXECUTION, MachO},
+	pe::PE,
+};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xc000_0002;
+const GNU_PROPERTY_X86_FEATURE_1_IBT: u32 = 0x1;
+const GNU_PROPERTY_X86_FEATURE_1_SHSTK: u32 = 0x2;
+const IMAGE_DLLCHARACTERISTICS_NX_COMPAT: u16 = 0x100;
+const IMAGE_DLLCHARACTERISTICS_GUARD_CF: u16 = 0x4000;
+
+/// Symbols which are only present when code is compiled with stack canaries
+const CANARY_SYMBOLS: &[&str] = &[
+	"__stack_chk_fail",
+	"__stack_chk_guard",
+	"__intel_security_cookie",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Relro {
+	None,
+	Partial,
+	Full,
+}
+
+/// The exploit mitigations a binary was built with, similar to those reported by `checksec`
+///
+/// Mitigations which don't apply to the format or architecture of the binary, or which can't be
+/// determined from its headers, are `None`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Mitigations {
+	/// Whether the binary may be loaded at a random address
+	pub pie: bool,
+	/// Whether the stack and data of the binary are non-executable
+	pub nx: bool,
+	pub relro: Option<Relro>,
+	pub canary: Option<bool>,
+	/// Indirect Branch Tracking - indirect branches must land on an `endbr` instruction
+	pub ibt: Option<bool>,
+	/// Shadow stacks - returns must match the call which they return from
+	pub shstk: Option<bool>,
+	/// Control Flow Guard - indirect calls are checked against a table of valid targets
+	pub cfg: Option<bool>,
+}
+
+impl Mitigations {
+	pub(crate) fn elf(e: &Elf, bytes: &[u8], pie: bool) -> Self {
+		// The stack is executable unless the binary asks otherwise
+		let nx = e
+			.program_headers
+			.iter()
+			.find(|header| header.p_type == PT_GNU_STACK)
+			.is_some_and(|header| header.p_flags & PF_X == 0);
+		// Older linkers mark immediate binding with its own dynamic tag rather than a flag
+		let bind_now = e.dynamic.as_ref().is_some_and(|dynamic| {
+			dynamic.info.flags & DF_BIND_NOW != 0
+				|| dynamic.info.flags_1 & DF_1_NOW != 0
+				|| dynamic.dyns.iter().any(|entry| entry.d_tag == DT_BIND_NOW)
+		});
+		let relro = match e.program_headers.iter().any(|header| header.p_type == PT_GNU_RELRO) {
+			true if bind_now => Relro::Full,
+			true => Relro::Partial,
+			false => Relro::None,
+		};
+		let canary = e
+			.syms
+			.iter()
+			.filter_map(|sym| e.strtab.get_at(sym.st_name))
+			.chain(e.dynsyms.iter().filter_map(|sym| e.dynstrtab.get_at(sym.st_name)))
+			.any(|name| CANARY_SYMBOLS.contains(&name));
+		let (ibt, shstk) = match e.header.e_machine {
+			EM_386 | EM_X86_64 => {
+				let features = x86_features(e, bytes);
+				(
+					Some(features & GNU_PROPERTY_X86_FEATURE_1_IBT != 0),
+					Some(features & GNU_PROPERTY_X86_FEATURE_1_SHSTK != 0),
+				)
+			}
+			_ => (None, None),
+		};
+		Self {
+			pie,
+			nx,
+			relro: Some(relro),
+			canary: Some(canary),
+			ibt,
+			shstk,
+			cfg: None,
+		}
+	}
+
+	pub(crate) fn pe(p: &PE, pie: bool) -> Self {
+		let characteristics = p
+			.header
+			.optional_header
+			.map(|header| header.windows_fields.dll_characteristics)
+			.unwrap_or(0);
+		Self {
+			pie,
+			nx: characteristics & IMAGE_DLLCHARACTERISTICS_NX_COMPAT != 0,
+			relro: None,
+			canary: None,
+			ibt: None,
+			shstk: None,
+			cfg: Some(characteristics & IMAGE_DLLCHARACTERISTICS_GUARD_CF != 0),
+		}
+	}
+
+	pub(crate) fn macho(m: &MachO, pie: bool) -> Self {
+		// Mach-O symbols have a leading underscore
+		let canary = m
+			.symbols()
+			.flatten()
+			.any(|(name, _)| CANARY_SYMBOLS.contains(&name.trim_start_matches('_')));
+		Self {
+			pie,
+			nx: m.header.flags & MH_ALLOW_STACK_EXECUTION == 0,
+			relro: None,
+			canary: Some(canary),
+			ibt: None,
+			shstk: None,
+			cfg: None,
+		}
+	}
+}
+
+impl Display for Mitigations {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let yes_no = |enabled: bool| if enabled { "yes" } else { "no" };
+		writeln!(f, "PIE:     {}", yes_no(self.pie))?;
+		writeln!(f, "NX:      {}", yes_no(self.nx))?;
+		if let Some(relro) = self.relro {
+			let relro = match relro {
+				Relro::None => "no",
+				Relro::Partial => "partial",
+				Relro::Full => "full",
+			};
+			writeln!(f, "RELRO:   {}", relro)?;
+		}
+		let optional = [
+			("Canary:", self.canary),
+			("IBT:", self.ibt),
+			("SHSTK:", self.shstk),
+			("CFG:", self.cfg),
+		];
+		for (name, enabled) in optional {
+			if let Some(enabled) = enabled {
+				writeln!(f, "{:<8} {}", name, yes_no(enabled))?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// The x86 features a binary opts into through the `.note.gnu.property` section, or zero if it
+/// has none
+fn x86_features(e: &Elf, bytes: &[u8]) -> u32 {
+	let word = |bytes: &[u8]| -> Option<u32> {
+		let bytes = bytes.get(..4)?.try_into().ok()?;
+		match e.little_endian {
+			true => Some(u32::from_le_bytes(bytes)),
+			false => Some(u32::from_be_bytes(bytes)),
+		}
+	};
+	// Each property is a type and size, followed by its data padded to the word size
+	let align = if e.is_64 { 8 } else { 4 };
+	let features = |desc: &[u8]| -> Option<u32> {
+		let mut offset = 0;
+		while offset + 8 <= desc.len() {
+			let kind = word(&desc[offset..])?;
+			let size = word(&desc[offset + 4..])? as usize;
+			let end = (offset + 8).checked_add(size)?;
+			if kind == GNU_PROPERTY_X86_FEATURE_1_AND {
+				return word(desc.get(offset + 8..end)?);
+			}
+			offset = (offset + 8).checked_add(size.checked_next_multiple_of(align)?)?;
+		}
+		None
+	};
+	elf_notes(e, bytes)
+		.into_iter()
+		.filter(|note| note.name == "GNU" && note.n_type == NT_GNU_PROPERTY_TYPE_0)
+		.find_map(|note| features(note.desc))
+		.unwrap_or(0)
+}
@@ -0,0 +1,252 @@
+use crate::{
+	binary::{Binary, Bitness, Section},
+	effects::Effects,
+	gadgets::Gadget,
+	rules::TailKind,
+};
+use serde::Serialize;
+use std::{
+	collections::HashMap,
+	io::{self, Write},
+};
+
+#[derive(Debug, Serialize)]
+pub struct InstructionRecord {
+	pub mnemonic: String,
+	pub operands: String,
+}
+
+/// Machine-readable description of a single gadget
+#[derive(Debug, Serialize)]
+pub struct GadgetRecord {
+	pub address: usize,
+	pub file_offset: Option<usize>,
+	pub section: Option<String>,
+	/// The function containing the gadget eg. `memcpy+0x1a`
+	pub symbol: Option<String>,
+	pub bytes: String,
+	pub instructions: Vec<InstructionRecord>,
+	pub tail: Option<TailKind>,
+	/// Whether the gadget returns through an address which a CET shadow stack wouldn't hold - only
+	/// given for binaries which opt into shadow stacks
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub violates_shadow_stack: Option<bool>,
+	pub effects: Effects,
+}
+
+impl GadgetRecord {
+	/// Builds a record for a gadget, looking up the section which contains it to fill in
+	/// location and encoding details
+	///
+	/// `shadow_stack` is whether the binary opts into shadow stacks, so that shadow stack
+	/// violations matter.
+	pub fn new(gadget: &Gadget, address: usize, sections: &[Section], shadow_stack: bool) -> Self {
+		let location = locate(gadget, address, sections);
+		let bytes = location
+			.and_then(|(section, _)| section.bytes_at(address, gadget.byte_len()))
+			.map(hex)
+			.unwrap_or_default();
+		let instructions = gadget
+			.format_parts()
+			.into_iter()
+			.map(|(mnemonic, operands)| InstructionRecord { mnemonic, operands })
+			.collect();
+		Self {
+			address,
+			file_offset: location.map(|(section, offset)| section.file_offset() + offset),
+			section: location.map(|(section, _)| section.name().to_string()),
+			symbol: location.and_then(|(section, _)| symbol_name(section, address)),
+			bytes,
+			instructions,
+			tail: gadget.tail_kind(),
+			violates_shadow_stack: shadow_stack.then(|| gadget.violates_shadow_stack()),
+			effects: gadget.effects(),
+		}
+	}
+}
+
+/// The section containing a gadget, along with the offset of the gadget within it
+pub fn locate<'s, 'b>(
+	gadget: &Gadget,
+	address: usize,
+	sections: &'s [Section<'b>],
+) -> Option<(&'s Section<'b>, usize)> {
+	sections
+		.iter()
+		.filter(|section| section.arch() == gadget.arch())
+		.find_map(|section| {
+			section
+				.address_to_offset(address)
+				.map(|offset| (section, offset))
+		})
+}
+
+/// Describes an address relative to the function containing it eg. `memcpy+0x1a`
+pub fn symbol_name(section: &Section, address: usize) -> Option<String> {
+	let name = match section.symbol(address)? {
+		(symbol, 0) => symbol.name().to_string(),
+		(symbol, offset) => format!("{}+{:#x}", symbol.name(), offset),
+	};
+	Some(name)
+}
+
+pub fn hex(bytes: &[u8]) -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() }
+
+/// Writes gadgets as a Python module of named constants for use with pwntools
+///
+/// Addresses within position independent ELF binaries are written relative to the `address` of a
+/// pwntools `ELF` so that they follow the binary when it is rebased. Addresses within other files,
+/// which pwntools can't load, are written as they are.
+pub fn write_python(
+	mut w: impl Write,
+	gadgets: &[(Gadget, usize)],
+	sections: &[Section],
+	binary: &Binary,
+) -> io::Result<()> {
+	let pack = Bitness::of(sections).pack_fn();
+	let elf = (binary.is_elf() && binary.is_position_independent().unwrap_or(false))
+		.then(|| python_identifier(binary));
+	// A pwntools `ELF` starts at the address it was linked at, and is then moved to the base
+	let load_base = binary.base().unwrap_or_else(|| binary.link_base());
+
+	match &elf {
+		Some(elf) => {
+			writeln!(w, "from pwn import ELF, {}", pack)?;
+			writeln!(w)?;
+			writeln!(w, "{} = ELF({:?}, checksec=False)", elf, binary.path())?;
+			if let Some(base) = binary.base() {
+				writeln!(w, "{}.address = {:#x}", elf, base)?;
+			}
+		}
+		None => writeln!(w, "from pwn import {}", pack)?,
+	}
+	writeln!(w)?;
+	writeln!(w, "pack = {}", pack)?;
+	writeln!(w)?;
+
+	let mut names = HashMap::new();
+	for (gadget, address) in gadgets {
+		let name = constant_name(gadget);
+		let count = names.entry(name.clone()).or_insert(0);
+		*count += 1;
+		let name = match *count {
+			1 => name,
+			n => format!("{}_{}", name, n),
+		};
+
+		let mut formatted = String::new();
+		gadget.format_instruction(&mut formatted);
+
+		match (&elf, address.checked_sub(load_base)) {
+			(Some(elf), Some(offset)) => writeln!(
+				w,
+				"{} = {}.address + {:#x}  # {}",
+				name, elf, offset, formatted
+			)?,
+			_ => writeln!(w, "{} = {:#x}  # {}", name, address, formatted)?,
+		}
+	}
+	Ok(())
+}
+
+/// Names a gadget after its instructions eg. `POP_RDI_RET` for `pop rdi; ret;`, with memory
+/// operands marked by `MEM` so that `mov rax, [rax]` isn't named like a register move
+fn constant_name(gadget: &Gadget) -> String {
+	let mut formatted = String::new();
+	gadget.format_instruction(&mut formatted);
+	let formatted = formatted.replace('[', " mem ");
+	let words = formatted
+		.split(|c: char| !c.is_ascii_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.collect::<Vec<_>>();
+	let name = words.join("_").to_ascii_uppercase();
+	match name.chars().next() {
+		Some(c) if c.is_ascii_alphabetic() => name,
+		_ => format!("GADGET_{}", name),
+	}
+}
+
+/// Names the pwntools `ELF` of a binary after its file eg. `libc` for `libc.so.6`
+fn python_identifier(binary: &Binary) -> String {
+	let name = binary
+		.path()
+		.file_name()
+		.and_then(|name| name.to_str())
+		.and_then(|name| name.split('.').next())
+		.unwrap_or_default()
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+		.collect::<String>()
+		.to_ascii_lowercase();
+	match name.chars().next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' => name,
+		_ => format!("elf{}", name),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use iced_x86::{Decoder, DecoderOptions};
+	use std::{env, fs, path::PathBuf};
+
+	const LINK_BASE: u64 = 0x400000;
+	/// `pop rdi; ret`, placed straight after the ELF and program headers
+	const CODE: [u8; 2] = [0x5f, 0xc3];
+	const CODE_OFFSET: u64 = 0x78;
+
+	/// Writes a position independent x86-64 ELF linked at `LINK_BASE` with a single segment
+	fn write_elf(name: &str) -> PathBuf {
+		let len = CODE_OFFSET + CODE.len() as u64;
+		let mut bytes = Vec::new();
+		bytes.extend(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+		bytes.extend(3u16.to_le_bytes()); // ET_DYN
+		bytes.extend(62u16.to_le_bytes()); // EM_X86_64
+		bytes.extend(1u32.to_le_bytes());
+		bytes.extend((LINK_BASE + CODE_OFFSET).to_le_bytes()); // e_entry
+		bytes.extend(64u64.to_le_bytes()); // e_phoff
+		bytes.extend(0u64.to_le_bytes()); // e_shoff
+		bytes.extend(0u32.to_le_bytes());
+		for half in [64u16, 56, 1, 64, 0, 0] {
+			bytes.extend(half.to_le_bytes());
+		}
+		bytes.extend(1u32.to_le_bytes()); // PT_LOAD
+		bytes.extend(5u32.to_le_bytes()); // PF_R | PF_X
+		for word in [0, LINK_BASE, LINK_BASE, len, len, 0x1000] {
+			bytes.extend(word.to_le_bytes());
+		}
+		bytes.extend(CODE);
+
+		let path = env::temp_dir().join(format!("ropr-{}-{}", name, std::process::id()));
+		fs::write(&path, bytes).unwrap();
+		path
+	}
+
+	fn python(binary: &Binary) -> String {
+		let sections = binary.sections(None, None).unwrap();
+		let section = &sections[0];
+		let address = section.program_base() + section.section_vaddr() + CODE_OFFSET as usize;
+		let instructions = Decoder::with_ip(64, &CODE, address as u64, DecoderOptions::NONE)
+			.into_iter()
+			.collect();
+		let gadgets = [(Gadget::x86(instructions, 0), address)];
+		let mut output = Vec::new();
+		write_python(&mut output, &gadgets, &sections, binary).unwrap();
+		String::from_utf8(output).unwrap()
+	}
+
+	#[test]
+	fn writes_addresses_relative_to_the_link_base() {
+		let path = write_elf("link-base");
+		let mut binary = Binary::new(&path).unwrap();
+		let name = python_identifier(&binary);
+		let expected = format!("POP_RDI_RET = {}.address + 0x78  # pop rdi; ret;", name);
+		assert!(python(&binary).contains(&expected));
+
+		binary.set_base(Some(0x5555_5555_4000));
+		let rebased = python(&binary);
+		assert!(rebased.contains(&format!("{}.address = 0x555555554000", name)));
+		assert!(rebased.contains(&expected));
+		fs::remove_file(path).unwrap();
+	}
+}
@@ -0,0 +1,527 @@
+use crate::{
+	binary::{Arch, Bitness, Section},
+	disassembler::Disassembler,
+	effects::register_name,
+	gadgets::{formatter, Gadget},
+	output::GadgetRecord,
+};
+use iced_x86::{Formatter, Instruction, Mnemonic, OpKind, Register};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// The registers popped by the ret2csu pop gadget, in order
+const CSU_POPS: [Register; 6] = [
+	Register::RBX,
+	Register::RBP,
+	Register::R12,
+	Register::R13,
+	Register::R14,
+	Register::R15,
+];
+
+/// The registers which pass the first six arguments of a function on x86-64
+const ARGUMENT_REGISTERS: [Register; 6] = [
+	Register::RDI,
+	Register::RSI,
+	Register::RDX,
+	Register::RCX,
+	Register::R8,
+	Register::R9,
+];
+
+/// The parameters of `__libc_start_main`, in the order they are passed
+const START_PARAMETERS: &[&str] = &[
+	"main",
+	"argc",
+	"argv",
+	"init",
+	"fini",
+	"rtld_fini",
+	"stack_end",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternKind {
+	/// The pair of gadgets at the end of `__libc_csu_init`, which load `rdx`, `rsi` and `edi` from
+	/// popped registers and call through a table of function pointers
+	Ret2csu,
+	/// The first PLT entry, which calls `_dl_runtime_resolve` to bind a symbol lazily
+	Ret2dlresolve,
+	/// `_start`, which calls `__libc_start_main` with the address of `main`
+	Start,
+	/// The tail of `__libc_start_main` which `main` returns into before it calls `exit`
+	MainReturn,
+}
+
+impl Display for PatternKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Self::Ret2csu => "ret2csu",
+			Self::Ret2dlresolve => "ret2dlresolve",
+			Self::Start => "_start",
+			Self::MainReturn => "__libc_start_main tail",
+		};
+		f.write_str(name)
+	}
+}
+
+/// A piece of code making up a pattern, such as the pop gadget of ret2csu
+#[derive(Debug)]
+pub struct PatternStep {
+	pub role: &'static str,
+	pub address: usize,
+	pub gadget: Gadget,
+}
+
+/// A register or argument set by a pattern, along with where its value comes from
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisterSource {
+	pub register: String,
+	pub source: String,
+}
+
+/// A well-known sequence of code which is used as a unit in exploits, often spanning several
+/// gadgets which are of little use apart
+#[derive(Debug)]
+pub struct Pattern {
+	kind: PatternKind,
+	steps: Vec<PatternStep>,
+	registers: Vec<RegisterSource>,
+	note: String,
+}
+
+impl Pattern {
+	pub fn kind(&self) -> PatternKind { self.kind }
+
+	pub fn steps(&self) -> &[PatternStep] { &self.steps }
+
+	pub fn registers(&self) -> &[RegisterSource] { &self.registers }
+
+	/// How the pattern is used
+	pub fn note(&self) -> &str { &self.note }
+}
+
+/// Machine-readable description of a pattern
+#[derive(Debug, Serialize)]
+pub struct PatternRecord {
+	pub kind: PatternKind,
+	pub steps: Vec<StepRecord>,
+	pub registers: Vec<RegisterSource>,
+	pub note: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepRecord {
+	pub role: &'static str,
+	#[serde(flatten)]
+	pub gadget: GadgetRecord,
+}
+
+impl PatternRecord {
+	/// `shadow_stack` is whether the binary opts into shadow stacks - see [`GadgetRecord::new`]
+	pub fn new(pattern: &Pattern, sections: &[Section], shadow_stack: bool) -> Self {
+		let steps = pattern
+			.steps
+			.iter()
+			.map(|step| StepRecord {
+				role: step.role,
+				gadget: GadgetRecord::new(&step.gadget, step.address, sections, shadow_stack),
+			})
+			.collect();
+		Self {
+			kind: pattern.kind,
+			steps,
+			registers: pattern.registers.clone(),
+			note: pattern.note.clone(),
+		}
+	}
+}
+
+/// Finds ret2csu, ret2dlresolve and `__libc_start_main` patterns within x86 sections, ordered by
+/// section and then by kind
+pub fn find_patterns(sections: &[Section]) -> Vec<Pattern> {
+	let mut patterns = Vec::new();
+	for section in sections.iter().filter(|section| section.arch() == Arch::X86) {
+		let mut decoder = SectionDecoder::new(section);
+		patterns.extend(ret2csu(&mut decoder));
+		patterns.extend(ret2dlresolve(&mut decoder));
+		patterns.extend(start(&mut decoder));
+		patterns.extend(main_return(&mut decoder));
+	}
+	patterns
+}
+
+/// Decodes runs of instructions at arbitrary offsets within a section
+struct SectionDecoder<'s> {
+	section: &'s Section<'s>,
+	disassembler: Disassembler<'s>,
+}
+
+impl<'s> SectionDecoder<'s> {
+	fn new(section: &'s Section<'s>) -> Self {
+		Self {
+			section,
+			disassembler: Disassembler::new(section.bitness(), section.bytes()),
+		}
+	}
+
+	fn address(&self, offset: usize) -> usize {
+		self.section.program_base() + self.section.section_vaddr() + offset
+	}
+
+	/// The offsets at which a pair of bytes satisfies `predicate`
+	fn offsets(&self, predicate: impl Fn(u8, u8) -> bool) -> Vec<usize> {
+		self.section
+			.bytes()
+			.windows(2)
+			.enumerate()
+			.filter(|(_, pair)| predicate(pair[0], pair[1]))
+			.map(|(offset, _)| offset)
+			.collect()
+	}
+
+	/// Decodes up to `count` consecutive instructions from an offset, stopping at the first
+	/// invalid one
+	fn decode(&mut self, offset: usize, count: usize) -> Vec<Instruction> {
+		let mut instructions = Vec::with_capacity(count);
+		let mut offset = offset;
+		while instructions.len() < count && offset < self.section.bytes().len() {
+			let mut instr = Instruction::default();
+			let ip = self.address(offset) as u64;
+			self.disassembler.decode_at_offset(ip, offset, &mut instr);
+			if instr.is_invalid() {
+				break;
+			}
+			offset += instr.len();
+			instructions.push(instr);
+		}
+		instructions
+	}
+}
+
+/// Finds `mov rdx, r14; mov rsi, r13; mov edi, r12d; call qword ptr [r15+rbx*8]` or a variant
+/// with other registers, falling through to `pop rbx; pop rbp; pop r12; pop r13; pop r14; pop r15;
+/// ret`
+fn ret2csu(decoder: &mut SectionDecoder) -> Vec<Pattern> {
+	if !matches!(decoder.section.bitness(), Bitness::Bits64) {
+		return Vec::new();
+	}
+	// The call is encoded as `41 ff 14 dc-df`, after three `mov`s of three bytes each
+	decoder
+		.offsets(|first, second| first == 0x41 && second == 0xff)
+		.into_iter()
+		.filter_map(|call| {
+			let start = call.checked_sub(9)?;
+			let body = decoder.decode(start, 4);
+			let (call_instr, moves) = body.split_last()?;
+			let loads_arguments = moves.len() == 3
+				&& moves.iter().all(is_csu_move)
+				&& [Register::RDX, Register::RSI, Register::RDI].iter().all(|register| {
+					moves
+						.iter()
+						.any(|instr| instr.op0_register().full_register() == *register)
+				});
+			let calls_table = call_instr.ip() as usize == decoder.address(call)
+				&& call_instr.mnemonic() == Mnemonic::Call
+				&& call_instr.op0_kind() == OpKind::Memory
+				&& call_instr.memory_index() == Register::RBX
+				&& call_instr.memory_index_scale() == 8
+				&& call_instr.memory_displacement64() == 0
+				&& CSU_POPS[2..].contains(&call_instr.memory_base());
+			if !loads_arguments || !calls_table {
+				return None;
+			}
+
+			// After the call, the loop counter is checked and the stack is adjusted before the
+			// registers are popped
+			let after = call + call_instr.len();
+			let tail = decoder.decode(after, 12);
+			let pops = (0..=5).find(|index| tail.get(*index..).is_some_and(is_csu_pops))?;
+			let skipped = tail[..pops]
+				.iter()
+				.filter(|instr| {
+					instr.mnemonic() == Mnemonic::Add && instr.op0_register() == Register::RSP
+				})
+				.filter_map(|instr| instr.try_immediate(1).ok())
+				.sum::<u64>() / 8 + CSU_POPS.len() as u64;
+
+			let mut registers = moves
+				.iter()
+				.map(|instr| RegisterSource {
+					register: register_name(instr.op0_register()),
+					source: register_name(instr.op1_register()),
+				})
+				.collect::<Vec<_>>();
+			registers.push(RegisterSource {
+				register: "rip".to_string(),
+				source: format!("[{}+rbx*8]", register_name(call_instr.memory_base())),
+			});
+			let pop_address = tail[pops].ip() as usize;
+			Some(Pattern {
+				kind: PatternKind::Ret2csu,
+				steps: vec![
+					PatternStep {
+						role: "pop",
+						address: pop_address,
+						gadget: Gadget::x86(tail[pops..pops + 7].to_vec(), 0),
+					},
+					PatternStep {
+						role: "call",
+						address: decoder.address(start),
+						gadget: Gadget::x86(body, 0),
+					},
+				],
+				registers,
+				note: format!(
+					"pop rbx = 0, rbp = 1 and the sources into r12-r15, then return into the call \
+					 gadget - the function pointer is called once and execution falls back into \
+					 the pop gadget, which takes {} words from the stack before returning",
+					skipped
+				),
+			})
+		})
+		.collect()
+}
+
+/// Whether an instruction copies one of the registers popped by ret2csu into an argument register
+fn is_csu_move(instr: &Instruction) -> bool {
+	instr.mnemonic() == Mnemonic::Mov
+		&& instr.op0_kind() == OpKind::Register
+		&& instr.op1_kind() == OpKind::Register
+		&& CSU_POPS[2..].contains(&instr.op1_register().full_register())
+}
+
+/// Whether instructions start with the pop gadget of ret2csu
+fn is_csu_pops(instructions: &[Instruction]) -> bool {
+	instructions.len() > CSU_POPS.len()
+		&& instructions.iter().zip(CSU_POPS).all(|(instr, register)| {
+			instr.mnemonic() == Mnemonic::Pop && instr.op0_register() == register
+		})
+		&& instructions[CSU_POPS.len()].mnemonic() == Mnemonic::Ret
+		&& instructions[CSU_POPS.len()].op_count() == 0
+}
+
+/// Finds the stub at the start of the PLT, which pushes the `link_map` from the second GOT entry
+/// and jumps to `_dl_runtime_resolve` through the third
+fn ret2dlresolve(decoder: &mut SectionDecoder) -> Vec<Pattern> {
+	let word_size = decoder.section.bitness().word_size() as u64;
+	// Pushes of memory operands are encoded as `ff /6`
+	decoder
+		.offsets(|first, second| first == 0xff && second & 0x38 == 0x30)
+		.into_iter()
+		.filter_map(|offset| {
+			let stub = decoder.decode(offset, 2);
+			let (push, jmp) = match stub.as_slice() {
+				[push, jmp] => (push, jmp),
+				_ => return None,
+			};
+			let is_slot = |instr: &Instruction| {
+				instr.op0_kind() == OpKind::Memory
+					&& instr.memory_index() == Register::None
+					&& matches!(
+						instr.memory_base(),
+						Register::None | Register::RIP | Register::EBX
+					)
+			};
+			let is_stub = push.mnemonic() == Mnemonic::Push
+				&& jmp.mnemonic() == Mnemonic::Jmp
+				&& is_slot(push)
+				&& is_slot(jmp)
+				&& push.memory_base() == jmp.memory_base()
+				&& jmp.memory_displacement64() == push.memory_displacement64() + word_size;
+			if !is_stub {
+				return None;
+			}
+
+			// Position independent i386 code addresses the GOT through `ebx`
+			let slot = |instr: &Instruction| match instr.memory_base() {
+				Register::EBX => format!("[ebx+{:#x}]", instr.memory_displacement64()),
+				_ => format!("[{:#x}]", instr.memory_displacement64()),
+			};
+			let registers = match push.memory_base() {
+				Register::EBX => vec![RegisterSource {
+					register: "ebx".to_string(),
+					source: "the address of the GOT".to_string(),
+				}],
+				_ => Vec::new(),
+			};
+			let note = format!(
+				"return into the stub with the index of a forged relocation as the next word on \
+				 the stack - it pushes the link_map at {} and jumps through {} to \
+				 _dl_runtime_resolve, which binds the symbol named by the relocation and jumps to \
+				 it. The resolver is only filled in for lazy binding, so not under full RELRO",
+				slot(push),
+				slot(jmp)
+			);
+			Some(Pattern {
+				kind: PatternKind::Ret2dlresolve,
+				steps: vec![PatternStep {
+					role: "stub",
+					address: decoder.address(offset),
+					gadget: Gadget::x86(stub, 0),
+				}],
+				registers,
+				note,
+			})
+		})
+		.collect()
+}
+
+/// Finds `_start`, which clears the frame pointer, pops `argc`, aligns the stack and then calls
+/// `__libc_start_main` followed by `hlt`
+fn start(decoder: &mut SectionDecoder) -> Vec<Pattern> {
+	let bitness = decoder.section.bitness();
+	// `xor ebp, ebp`
+	decoder
+		.offsets(|first, second| first == 0x31 && second == 0xed)
+		.into_iter()
+		.filter_map(|offset| {
+			let instructions = decoder.decode(offset, 20);
+			// Position independent i386 code calls a thunk to find the GOT first
+			let call = instructions.windows(2).position(|pair| {
+				pair[0].mnemonic() == Mnemonic::Call && pair[1].mnemonic() == Mnemonic::Hlt
+			})?;
+			let body = &instructions[..call];
+			let pops_argc = body.iter().any(|instr| {
+				instr.mnemonic() == Mnemonic::Pop
+					&& instr.op0_register().full_register() == Register::RSI
+			});
+			let aligns_stack = body.iter().any(|instr| {
+				instr.mnemonic() == Mnemonic::And
+					&& instr.op0_register().full_register() == Register::RSP
+			});
+			if !pops_argc || !aligns_stack {
+				return None;
+			}
+
+			let registers = match bitness {
+				Bitness::Bits64 => ARGUMENT_REGISTERS
+					.iter()
+					.zip(START_PARAMETERS)
+					.filter_map(|(register, parameter)| {
+						let instr = body.iter().rev().find(|instr| {
+							instr.op0_kind() == OpKind::Register
+								&& instr.op0_register().full_register() == *register
+						})?;
+						Some(RegisterSource {
+							register: format!("{} ({})", register_name(*register), parameter),
+							source: source(instr),
+						})
+					})
+					.collect(),
+				// Arguments are pushed from last to first
+				Bitness::Bits32 => body
+					.iter()
+					.filter(|instr| instr.mnemonic() == Mnemonic::Push)
+					.rev()
+					.zip(START_PARAMETERS)
+					.enumerate()
+					.map(|(index, (instr, parameter))| RegisterSource {
+						register: match index {
+							0 => format!("[esp] ({})", parameter),
+							_ => format!("[esp+{:#x}] ({})", index * 4, parameter),
+						},
+						source: source(instr),
+					})
+					.collect(),
+			};
+			Some(Pattern {
+				kind: PatternKind::Start,
+				steps: vec![PatternStep {
+					role: "start",
+					address: decoder.address(offset),
+					gadget: Gadget::x86(instructions[..=call].to_vec(), 0),
+				}],
+				registers,
+				note: "return here to run main again - argc is popped from the stack and argv is \
+				       the stack pointer after it"
+					.to_string(),
+			})
+		})
+		.collect()
+}
+
+/// Finds `call rax; mov edi, eax; call exit`, where `__libc_start_main` calls `main` and exits
+/// with its return value
+fn main_return(decoder: &mut SectionDecoder) -> Vec<Pattern> {
+	if !matches!(decoder.section.bitness(), Bitness::Bits64) {
+		return Vec::new();
+	}
+	// `call` of a register is encoded as `ff d0-d7`
+	decoder
+		.offsets(|first, second| first == 0xff && second & 0xf8 == 0xd0)
+		.into_iter()
+		.filter_map(|offset| {
+			let instructions = decoder.decode(offset, 3);
+			let (call, mov, exit) = match instructions.as_slice() {
+				[call, mov, exit] => (call, mov, exit),
+				_ => return None,
+			};
+			let is_tail = mov.mnemonic() == Mnemonic::Mov
+				&& mov.op0_register() == Register::EDI
+				&& mov.op1_register() == Register::EAX
+				&& exit.mnemonic() == Mnemonic::Call
+				&& exit.op0_kind() == OpKind::NearBranch64;
+			// Without symbols any call is taken to be `exit`
+			let section = decoder.section;
+			let calls_exit = section.symbols().is_empty()
+				|| section
+					.symbol(exit.near_branch_target() as usize)
+					.is_some_and(|(symbol, offset)| offset == 0 && symbol.name() == "exit");
+			if !is_tail || !calls_exit {
+				return None;
+			}
+			Some(Pattern {
+				kind: PatternKind::MainReturn,
+				steps: vec![PatternStep {
+					role: "return",
+					address: decoder.address(offset + call.len()),
+					gadget: Gadget::x86(vec![*mov, *exit], 0),
+				}],
+				registers: vec![RegisterSource {
+					register: "edi".to_string(),
+					source: "eax".to_string(),
+				}],
+				note: "main returns here, so this is the return address above its frame - a leak \
+				       of it reveals where libc is loaded, and returning here exits with eax"
+					.to_string(),
+			})
+		})
+		.collect()
+}
+
+/// Describes the value an instruction loads into its destination, or pushes
+fn source(instr: &Instruction) -> String {
+	let operand = match instr.mnemonic() {
+		Mnemonic::Pop => match instr.op0_register().size() {
+			8 => return "[rsp]".to_string(),
+			_ => return "[esp]".to_string(),
+		},
+		Mnemonic::Push => 0,
+		_ => 1,
+	};
+	let zeroes = matches!(instr.mnemonic(), Mnemonic::Xor | Mnemonic::Sub)
+		&& instr.op1_kind() == OpKind::Register
+		&& instr.op1_register() == instr.op0_register();
+	if zeroes {
+		return "0".to_string();
+	}
+	let absolute = instr.memory_index() == Register::None
+		&& matches!(instr.memory_base(), Register::None | Register::RIP | Register::EIP);
+	match instr.op_kind(operand) {
+		OpKind::Register => register_name(instr.op_register(operand)),
+		OpKind::Memory if absolute => match instr.mnemonic() {
+			Mnemonic::Lea => format!("{:#x}", instr.memory_displacement64()),
+			_ => format!("[{:#x}]", instr.memory_displacement64()),
+		},
+		_ => match instr.try_immediate(operand) {
+			Ok(immediate) => format!("{:#x}", immediate),
+			Err(_) => {
+				let mut output = String::new();
+				let _ = formatter().format_operand(instr, &mut output, operand);
+				output
+			}
+		},
+	}
+}
@@ -0,0 +1,128 @@
+use crate::{
+	binary::{elf_arches, Arch, Bitness, Endian, Section},
+	error::Result,
+};
+use goblin::elf::{
+	header::{EI_CLASS, EI_DATA, ELFCLASS64, ELFDATA2LSB},
+	Elf,
+};
+use std::{
+	fs::{read_to_string, File},
+	io::{Read, Seek, SeekFrom},
+	sync::Arc,
+};
+
+/// The largest ELF header, which is all that is needed to identify the architecture of a process
+const MAX_HEADER_SIZE: usize = 64;
+
+/// The executable memory of a running Linux process, read through `/proc/<pid>`
+///
+/// Gadgets are found at the addresses they are mapped at in the process, so JIT compiled code
+/// and code which is decrypted at runtime can be searched as well.
+pub struct Process {
+	pid: u32,
+	bitness: Bitness,
+	arches: &'static [Arch],
+	endian: Endian,
+	mappings: Vec<Mapping>,
+}
+
+/// An executable mapping of the process, along with a copy of its memory
+struct Mapping {
+	start: usize,
+	/// Offset of the mapping within its backing file
+	offset: usize,
+	/// The backing file, or a name such as `[vdso]` or `[anon]` for other memory
+	name: String,
+	bytes: Vec<u8>,
+}
+
+impl Process {
+	/// Copies the executable mappings of a process, using the header of its executable to find
+	/// its architecture
+	///
+	/// Mappings which can't be read, such as `[vsyscall]`, are skipped.
+	pub fn new(pid: u32) -> Result<Self> {
+		let mut header = [0; MAX_HEADER_SIZE];
+		File::open(format!("/proc/{}/exe", pid))?.read_exact(&mut header)?;
+		let header = Elf::parse_header(&header)?;
+		let arches = elf_arches(&header)?;
+		let bitness = match header.e_ident[EI_CLASS] {
+			ELFCLASS64 => Bitness::Bits64,
+			_ => Bitness::Bits32,
+		};
+		let endian = match header.e_ident[EI_DATA] {
+			ELFDATA2LSB => Endian::Little,
+			_ => Endian::Big,
+		};
+
+		let maps = read_to_string(format!("/proc/{}/maps", pid))?;
+		let mut mem = File::open(format!("/proc/{}/mem", pid))?;
+		let mappings = maps
+			.lines()
+			.filter_map(parse_mapping)
+			.filter_map(|(start, end, offset, name)| {
+				let mut bytes = vec![0; end - start];
+				mem.seek(SeekFrom::Start(start as u64)).ok()?;
+				mem.read_exact(&mut bytes).ok()?;
+				Some(Mapping {
+					start,
+					offset,
+					name,
+					bytes,
+				})
+			})
+			.collect();
+
+		Ok(Self {
+			pid,
+			bitness,
+			arches,
+			endian,
+			mappings,
+		})
+	}
+
+	pub fn pid(&self) -> u32 { self.pid }
+
+	/// The executable mappings of the process, named after their backing files
+	pub fn sections(&self) -> Vec<Section<'_>> {
+		self.mappings
+			.iter()
+			.flat_map(|mapping| self.arches.iter().map(move |&arch| (mapping, arch)))
+			.map(|(mapping, arch)| Section {
+				name: mapping.name.clone(),
+				file_offset: mapping.offset,
+				section_vaddr: mapping.start,
+				program_base: 0,
+				bitness: self.bitness,
+				arch,
+				endian: self.endian,
+				bytes: &mapping.bytes,
+				symbols: Arc::default(),
+			})
+			.collect()
+	}
+}
+
+/// Parses a line of `/proc/<pid>/maps` such as
+/// `7f2e1c028000-7f2e1c1bd000 r-xp 00028000 103:02 1835082    /usr/lib/libc.so.6`, returning
+/// the range, file offset and name of executable mappings
+fn parse_mapping(line: &str) -> Option<(usize, usize, usize, String)> {
+	let mut fields = line.splitn(6, ' ');
+	let (start, end) = fields.next()?.split_once('-')?;
+	let permissions = fields.next()?;
+	let offset = fields.next()?;
+	if !permissions.contains('x') {
+		return None;
+	}
+	let start = usize::from_str_radix(start, 16).ok()?;
+	let end = usize::from_str_radix(end, 16).ok()?;
+	let offset = usize::from_str_radix(offset, 16).ok()?;
+	let name = fields.nth(2).map(str::trim).unwrap_or_default();
+	let name = match name {
+		"" => "[anon]".to_string(),
+		name => name.to_string(),
+	};
+	Some((start, end, offset, name))
+}
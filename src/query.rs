@@ -0,0 +1,472 @@
+use crate::{
+	effects::{register_name, Effects},
+	error::{Error, Result},
+	gadgets::Gadget,
+	rules::TailKind,
+};
+use iced_x86::Register;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Comparison {
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+}
+
+impl Comparison {
+	fn parse(s: &str) -> Option<Self> {
+		let comparison = match s {
+			"==" | "=" => Self::Eq,
+			"!=" => Self::Ne,
+			"<" => Self::Lt,
+			"<=" => Self::Le,
+			">" => Self::Gt,
+			">=" => Self::Ge,
+			_ => return None,
+		};
+		Some(comparison)
+	}
+
+	fn compare(self, lhs: i64, rhs: i64) -> bool {
+		match self {
+			Self::Eq => lhs == rhs,
+			Self::Ne => lhs != rhs,
+			Self::Lt => lhs < rhs,
+			Self::Le => lhs <= rhs,
+			Self::Gt => lhs > rhs,
+			Self::Ge => lhs >= rhs,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Expr {
+	And(Box<Expr>, Box<Expr>),
+	Or(Box<Expr>, Box<Expr>),
+	Not(Box<Expr>),
+	Reads(Register),
+	Writes(Register),
+	Clobbers(Register),
+	Mnemonic(String),
+	StackDelta(Comparison, i64),
+	Len(Comparison, i64),
+	Tail(TailKind),
+	MemRead,
+	MemWrite,
+	Flags,
+	Pivot,
+}
+
+impl Expr {
+	fn needs_effects(&self) -> bool {
+		match self {
+			Self::And(lhs, rhs) | Self::Or(lhs, rhs) => lhs.needs_effects() || rhs.needs_effects(),
+			Self::Not(expr) => expr.needs_effects(),
+			Self::Reads(_)
+			| Self::Writes(_)
+			| Self::Clobbers(_)
+			| Self::StackDelta(..)
+			| Self::MemRead
+			| Self::MemWrite
+			| Self::Flags => true,
+			Self::Mnemonic(_) | Self::Len(..) | Self::Tail(_) | Self::Pivot => false,
+		}
+	}
+
+	fn eval(&self, gadget: &Gadget, effects: &Effects) -> bool {
+		match self {
+			Self::And(lhs, rhs) => lhs.eval(gadget, effects) && rhs.eval(gadget, effects),
+			Self::Or(lhs, rhs) => lhs.eval(gadget, effects) || rhs.eval(gadget, effects),
+			Self::Not(expr) => !expr.eval(gadget, effects),
+			Self::Reads(reg) => contains(&effects.reads, *reg),
+			// The stack pointer isn't tracked as a register, so writing it means pivoting
+			Self::Writes(reg) if is_stack_pointer(*reg) => gadget.is_stack_pivot(),
+			Self::Writes(reg) => {
+				contains(&effects.writes, *reg) || contains(&effects.clobbers, *reg)
+			}
+			Self::Clobbers(reg) => contains(&effects.clobbers, *reg),
+			Self::Mnemonic(mnemonic) => gadget
+				.format_parts()
+				.iter()
+				.any(|(m, _)| m.eq_ignore_ascii_case(mnemonic)),
+			Self::StackDelta(comparison, value) => effects
+				.stack_delta
+				.is_some_and(|delta| comparison.compare(delta, *value)),
+			Self::Len(comparison, value) => comparison.compare(gadget.len() as i64, *value),
+			Self::Tail(kind) => gadget.tail_kind() == Some(*kind),
+			Self::MemRead => effects.memory_read,
+			Self::MemWrite => effects.memory_write,
+			Self::Flags => effects.flags_modified,
+			Self::Pivot => gadget.is_stack_pivot(),
+		}
+	}
+}
+
+/// Whether a list of full registers from `Effects` includes any part of `reg`
+fn contains(regs: &[Register], reg: Register) -> bool {
+	regs.iter()
+		.any(|r| r.full_register() == reg.full_register())
+}
+
+fn is_stack_pointer(reg: Register) -> bool { reg.full_register() == Register::RSP }
+
+/// A predicate over gadgets written in a small query language, eg.
+/// `writes(rdi) and not writes(rsp) and stack_delta <= 24 and tail == ret and no_mem_write`
+///
+/// Queries combine the following terms with `and`, `or`, `not` and parentheses:
+///
+/// - `reads(reg)`, `writes(reg)` and `clobbers(reg)` test the registers in the gadget's
+///   `Effects`, where any part of a register matches the whole (`writes(edi)` is true for a
+///   gadget writing `rdi`). `writes(rsp)` is true for stack pivots.
+/// - `mnemonic(name)` is true if any instruction has the given mnemonic.
+/// - `stack_delta` and `len` (the number of instructions) can be compared with `==`, `!=`, `<`,
+///   `<=`, `>` and `>=`. Comparisons against an unknown stack delta are always false.
+/// - `tail == ret`, `tail == syscall` and `tail == jop` test how the gadget ends.
+/// - `mem_read`, `mem_write`, `flags` and `pivot` test the corresponding effects, and may be
+///   prefixed with `no_` to negate them.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct GadgetFilter {
+	expr: Expr,
+}
+
+impl GadgetFilter {
+	pub fn parse(s: &str) -> Result<Self> {
+		let tokens = tokenize(s)?;
+		let mut parser = Parser {
+			query: s,
+			tokens: &tokens,
+		};
+		let expr = parser.or()?;
+		if let Some(token) = parser.tokens.first() {
+			return Err(parser.error(&format!("unexpected `{}`", token)));
+		}
+		Ok(Self { expr })
+	}
+
+	pub fn matches(&self, gadget: &Gadget) -> bool {
+		let effects = match self.expr.needs_effects() {
+			true => gadget.effects(),
+			false => Effects::default(),
+		};
+		self.expr.eval(gadget, &effects)
+	}
+}
+
+fn tokenize(s: &str) -> Result<Vec<String>> {
+	let mut tokens = Vec::new();
+	let mut chars = s.char_indices().peekable();
+	while let Some((start, c)) = chars.next() {
+		let mut end = start + c.len_utf8();
+		match c {
+			_ if c.is_whitespace() => continue,
+			'(' | ')' => (),
+			'=' | '!' | '<' | '>' => {
+				if let Some((i, '=')) = chars.peek() {
+					end = i + 1;
+					chars.next();
+				}
+			}
+			_ if is_word(c) => {
+				while let Some(&(i, c)) = chars.peek() {
+					if !is_word(c) {
+						break;
+					}
+					end = i + c.len_utf8();
+					chars.next();
+				}
+			}
+			_ => {
+				return Err(Error::InvalidQuery(format!(
+					"unexpected `{}` in `{}`",
+					c, s
+				)))
+			}
+		}
+		tokens.push(s[start..end].to_string());
+	}
+	Ok(tokens)
+}
+
+fn is_word(c: char) -> bool { c.is_alphanumeric() || matches!(c, '_' | '.' | '-') }
+
+struct Parser<'q, 't> {
+	query: &'q str,
+	tokens: &'t [String],
+}
+
+impl<'t> Parser<'_, 't> {
+	fn error(&self, message: &str) -> Error {
+		Error::InvalidQuery(format!("{} in `{}`", message, self.query))
+	}
+
+	fn next(&mut self) -> Result<&'t str> {
+		let (token, rest) = self
+			.tokens
+			.split_first()
+			.ok_or_else(|| self.error("unexpected end of query"))?;
+		self.tokens = rest;
+		Ok(token)
+	}
+
+	fn eat(&mut self, expected: &str) -> bool {
+		match self.tokens.first() {
+			Some(token) if token == expected => {
+				self.tokens = &self.tokens[1..];
+				true
+			}
+			_ => false,
+		}
+	}
+
+	fn expect(&mut self, expected: &str) -> Result<()> {
+		match self.eat(expected) {
+			true => Ok(()),
+			false => Err(self.error(&format!("expected `{}`", expected))),
+		}
+	}
+
+	fn or(&mut self) -> Result<Expr> {
+		let mut expr = self.and()?;
+		while self.eat("or") {
+			expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+		}
+		Ok(expr)
+	}
+
+	fn and(&mut self) -> Result<Expr> {
+		let mut expr = self.unary()?;
+		while self.eat("and") {
+			expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+		}
+		Ok(expr)
+	}
+
+	fn unary(&mut self) -> Result<Expr> {
+		if self.eat("not") {
+			return Ok(Expr::Not(Box::new(self.unary()?)));
+		}
+		if self.eat("(") {
+			let expr = self.or()?;
+			self.expect(")")?;
+			return Ok(expr);
+		}
+		self.term()
+	}
+
+	fn term(&mut self) -> Result<Expr> {
+		let name = self.next()?.to_lowercase();
+		let expr = match name.as_str() {
+			"reads" => Expr::Reads(self.register_argument()?),
+			"writes" => Expr::Writes(self.register_argument()?),
+			"clobbers" => Expr::Clobbers(self.register_argument()?),
+			"mnemonic" => {
+				self.expect("(")?;
+				let mnemonic = self.next()?.to_string();
+				self.expect(")")?;
+				Expr::Mnemonic(mnemonic)
+			}
+			"stack_delta" => {
+				let (comparison, value) = self.comparison()?;
+				Expr::StackDelta(comparison, value)
+			}
+			"len" => {
+				let (comparison, value) = self.comparison()?;
+				Expr::Len(comparison, value)
+			}
+			"tail" => {
+				let negate = match self.next()? {
+					"==" | "=" => false,
+					"!=" => true,
+					_ => return Err(self.error("expected `==` or `!=` after `tail`")),
+				};
+				let kind = match self.next()? {
+					"ret" => TailKind::Ret,
+					"syscall" => TailKind::Syscall,
+					"jop" => TailKind::Jop,
+					kind => return Err(self.error(&format!("unknown tail kind `{}`", kind))),
+				};
+				match negate {
+					true => Expr::Not(Box::new(Expr::Tail(kind))),
+					false => Expr::Tail(kind),
+				}
+			}
+			_ => {
+				let (negate, flag) = match name.strip_prefix("no_") {
+					Some(flag) => (true, flag),
+					None => (false, name.as_str()),
+				};
+				let expr = match flag {
+					"mem_read" => Expr::MemRead,
+					"mem_write" => Expr::MemWrite,
+					"flags" => Expr::Flags,
+					"pivot" => Expr::Pivot,
+					_ => return Err(self.error(&format!("unknown term `{}`", name))),
+				};
+				match negate {
+					true => Expr::Not(Box::new(expr)),
+					false => expr,
+				}
+			}
+		};
+		Ok(expr)
+	}
+
+	fn register_argument(&mut self) -> Result<Register> {
+		self.expect("(")?;
+		let name = self.next()?.to_lowercase();
+		let register = Register::values()
+			.filter(|r| r.is_gpr())
+			.find(|r| register_name(*r) == name)
+			.ok_or_else(|| self.error(&format!("unknown register `{}`", name)))?;
+		self.expect(")")?;
+		Ok(register)
+	}
+
+	fn comparison(&mut self) -> Result<(Comparison, i64)> {
+		let comparison = Comparison::parse(self.next()?)
+			.ok_or_else(|| self.error("expected a comparison operator"))?;
+		let value = self.next()?;
+		let (negative, digits) = match value.strip_prefix('-') {
+			Some(digits) => (true, digits),
+			None => (false, value),
+		};
+		let value = match digits.strip_prefix("0x") {
+			Some(hex) => i64::from_str_radix(hex, 16).ok(),
+			None => digits.parse().ok(),
+		}
+		.ok_or_else(|| self.error(&format!("invalid number `{}`", value)))?;
+		Ok((comparison, if negative { -value } else { value }))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use iced_x86::{Decoder, DecoderOptions};
+
+	fn expr(query: &str) -> Expr { GadgetFilter::parse(query).unwrap().expr }
+
+	fn error(query: &str) -> String { GadgetFilter::parse(query).unwrap_err().to_string() }
+
+	/// Decodes a 64-bit gadget from its encoding
+	fn gadget(bytes: &[u8]) -> Gadget {
+		let instructions = Decoder::new(64, bytes, DecoderOptions::NONE)
+			.into_iter()
+			.collect();
+		Gadget::x86(instructions, 0)
+	}
+
+	fn not(expr: Expr) -> Expr { Expr::Not(Box::new(expr)) }
+
+	fn and(lhs: Expr, rhs: Expr) -> Expr { Expr::And(Box::new(lhs), Box::new(rhs)) }
+
+	fn or(lhs: Expr, rhs: Expr) -> Expr { Expr::Or(Box::new(lhs), Box::new(rhs)) }
+
+	#[test]
+	fn tokenizes_operators_and_words() {
+		let tokens = tokenize("stack_delta<=0x18 and(len!=2)").unwrap();
+		assert_eq!(
+			tokens,
+			["stack_delta", "<=", "0x18", "and", "(", "len", "!=", "2", ")"]
+		);
+	}
+
+	#[test]
+	fn and_binds_tighter_than_or() {
+		assert_eq!(
+			expr("mem_read or mem_write and flags"),
+			or(Expr::MemRead, and(Expr::MemWrite, Expr::Flags))
+		);
+		assert_eq!(
+			expr("mem_read and mem_write or flags"),
+			or(and(Expr::MemRead, Expr::MemWrite), Expr::Flags)
+		);
+	}
+
+	#[test]
+	fn not_binds_tighter_than_and() {
+		assert_eq!(
+			expr("not mem_read and flags"),
+			and(not(Expr::MemRead), Expr::Flags)
+		);
+		assert_eq!(expr("not not pivot"), not(not(Expr::Pivot)));
+	}
+
+	#[test]
+	fn parentheses_group() {
+		assert_eq!(
+			expr("(mem_read or mem_write) and flags"),
+			and(or(Expr::MemRead, Expr::MemWrite), Expr::Flags)
+		);
+		assert_eq!(
+			expr("not (mem_read or pivot)"),
+			not(or(Expr::MemRead, Expr::Pivot))
+		);
+	}
+
+	#[test]
+	fn parses_terms() {
+		assert_eq!(expr("writes(RDI)"), Expr::Writes(Register::RDI));
+		assert_eq!(expr("reads(r8d)"), Expr::Reads(Register::R8D));
+		assert_eq!(expr("no_mem_write"), not(Expr::MemWrite));
+		assert_eq!(expr("tail != jop"), not(Expr::Tail(TailKind::Jop)));
+		assert_eq!(
+			expr("stack_delta >= -0x10"),
+			Expr::StackDelta(Comparison::Ge, -16)
+		);
+		assert_eq!(expr("len = 3"), Expr::Len(Comparison::Eq, 3));
+		assert_eq!(expr("mnemonic(pop)"), Expr::Mnemonic("pop".to_string()));
+	}
+
+	#[test]
+	fn reports_errors() {
+		assert_eq!(
+			error("frobs"),
+			"invalid query: unknown term `frobs` in `frobs`"
+		);
+		assert_eq!(
+			error("writes(xyz)"),
+			"invalid query: unknown register `xyz` in `writes(xyz)`"
+		);
+		assert_eq!(error("(pivot"), "invalid query: expected `)` in `(pivot`");
+		assert_eq!(
+			error("pivot flags"),
+			"invalid query: unexpected `flags` in `pivot flags`"
+		);
+		assert_eq!(
+			error("pivot and"),
+			"invalid query: unexpected end of query in `pivot and`"
+		);
+		assert_eq!(
+			error("len ~ 2"),
+			"invalid query: unexpected `~` in `len ~ 2`"
+		);
+		assert_eq!(
+			error("len == two"),
+			"invalid query: invalid number `two` in `len == two`"
+		);
+		assert_eq!(
+			error("tail == iret"),
+			"invalid query: unknown tail kind `iret` in `tail == iret`"
+		);
+	}
+
+	#[test]
+	fn matches_gadgets() {
+		let pop_rdi = gadget(&[0x5f, 0xc3]); // pop rdi; ret
+		let load = gadget(&[0x48, 0x8b, 0x00, 0xc3]); // mov rax, [rax]; ret
+		let filter = GadgetFilter::parse("writes(edi) and stack_delta == 16 and tail == ret");
+		let filter = filter.unwrap();
+		assert!(filter.matches(&pop_rdi));
+		assert!(!filter.matches(&load));
+		let filter = GadgetFilter::parse("mem_read and not writes(rdi)").unwrap();
+		assert!(!filter.matches(&pop_rdi));
+		assert!(filter.matches(&load));
+		let filter = GadgetFilter::parse("len < 2 or mnemonic(MOV)").unwrap();
+		assert!(!filter.matches(&pop_rdi));
+		assert!(filter.matches(&load));
+	}
+}
@@ -1,4 +1,27 @@
-use iced_x86::{Code, FlowControl, Instruction, Mnemonic, OpKind, Register};
+pub mod aarch64;
+pub mod arm;
+pub mod mips;
+pub mod powerpc;
+pub mod riscv;
+
+use iced_x86::{
+	Code, FlowControl, Instruction, InstructionInfoFactory, Mnemonic, OpAccess, OpKind, Register,
+};
+use serde::{Deserialize, Serialize};
+
+/// `rt_sigreturn` on x86-64
+const SIGRETURN_64: u64 = 15;
+/// `sigreturn` on i386, which restores the plain `sigframe` rather than the `rt_sigframe` restored
+/// by `rt_sigreturn`
+const SIGRETURN_32: u64 = 119;
+
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TailKind {
+	Ret,
+	Syscall,
+	Jop,
+}
 
 fn is_ret(instr: &Instruction) -> bool { matches!(instr.mnemonic(), Mnemonic::Ret) }
 
@@ -12,6 +35,74 @@ fn is_sys(instr: &Instruction) -> bool {
 	}
 }
 
+/// Whether x86 instructions load a `sigreturn` number into `eax` and then make the syscall
+pub fn is_sigreturn(instructions: &[Instruction]) -> bool {
+	let (tail, body) = match instructions.split_last() {
+		Some(split) => split,
+		None => return false,
+	};
+	let eax = match eax_after(body) {
+		Some(eax) => eax,
+		None => return false,
+	};
+	match tail.mnemonic() {
+		Mnemonic::Syscall => eax == SIGRETURN_64,
+		Mnemonic::Int => tail.try_immediate(0).ok() == Some(0x80) && eax == SIGRETURN_32,
+		_ => false,
+	}
+}
+
+/// The value of `eax` after a sequence of instructions, if they load a known constant into it
+fn eax_after(instructions: &[Instruction]) -> Option<u64> {
+	let mut factory = InstructionInfoFactory::new();
+	let mut eax = None;
+	// Immediate pushed by the previous instruction, so that `push 15; pop rax` is followed
+	let mut pushed = None;
+	for instr in instructions {
+		let op0 = match instr.op0_kind() {
+			OpKind::Register => instr.op0_register(),
+			_ => Register::None,
+		};
+		let immediate = match instr.op_count() {
+			2 => instr.try_immediate(1).ok(),
+			_ => None,
+		};
+		let zeroes = instr.op1_kind() == OpKind::Register && instr.op1_register() == op0;
+		let next = match (instr.mnemonic(), op0) {
+			(Mnemonic::Mov, Register::RAX | Register::EAX) => immediate.map(|i| i & 0xffff_ffff),
+			(Mnemonic::Mov, Register::AX) => eax.zip(immediate).map(|(e, i)| merge(e, i, 0xffff)),
+			(Mnemonic::Mov, Register::AL) => eax.zip(immediate).map(|(e, i)| merge(e, i, 0xff)),
+			(Mnemonic::Xor | Mnemonic::Sub, Register::RAX | Register::EAX) if zeroes => Some(0),
+			(Mnemonic::Pop, Register::RAX | Register::EAX) => pushed.map(|p| p & 0xffff_ffff),
+			_ => {
+				let writes_eax = factory.info(instr).used_registers().iter().any(|used| {
+					used.register().full_register() == Register::RAX
+						&& matches!(
+							used.access(),
+							OpAccess::Write
+								| OpAccess::CondWrite
+								| OpAccess::ReadWrite
+								| OpAccess::ReadCondWrite
+						)
+				});
+				match writes_eax {
+					true => None,
+					false => eax,
+				}
+			}
+		};
+		pushed = match instr.mnemonic() {
+			Mnemonic::Push => instr.try_immediate(0).ok(),
+			_ => None,
+		};
+		eax = next;
+	}
+	eax
+}
+
+/// Replaces the bits of `value` selected by `mask` with those of `low`
+fn merge(value: u64, low: u64, mask: u64) -> u64 { value & !mask | low & mask }
+
 fn is_jop(instr: &Instruction, noisy: bool) -> bool {
 	match instr.mnemonic() {
 		Mnemonic::Jmp => {
@@ -69,6 +160,19 @@ pub fn is_gadget_tail(instr: &Instruction, rop: bool, sys: bool, jop: bool, nois
 	false
 }
 
+/// Classifies an instruction which has already been accepted as a gadget tail
+pub fn tail_kind(instr: &Instruction) -> TailKind {
+	if is_ret(instr) {
+		TailKind::Ret
+	}
+	else if is_sys(instr) {
+		TailKind::Syscall
+	}
+	else {
+		TailKind::Jop
+	}
+}
+
 pub fn is_rop_gadget_head(instr: &Instruction, noisy: bool) -> bool {
 	if is_invalid(instr) {
 		return false;
@@ -150,6 +254,26 @@ pub fn is_stack_pivot_head(instr: &Instruction) -> bool {
 
 pub fn is_stack_pivot_tail(instr: &Instruction) -> bool { is_ret(instr) }
 
+/// Whether an indirect branch may land on the instruction when Indirect Branch Tracking is
+/// enforced
+pub fn is_branch_target(instr: &Instruction) -> bool {
+	matches!(instr.mnemonic(), Mnemonic::Endbr64 | Mnemonic::Endbr32)
+}
+
+/// Whether the instruction faults or enters the kernel when run in user mode, such as `out`,
+/// `hlt`, `int3` or `ud2`, so that execution can't carry on past it
+pub fn traps_in_user_mode(instr: &Instruction) -> bool {
+	is_invalid(instr)
+		|| instr.is_privileged()
+		|| matches!(instr.flow_control(), FlowControl::Interrupt | FlowControl::Exception)
+		|| matches!(instr.mnemonic(), Mnemonic::Syscall | Mnemonic::Sysenter)
+}
+
+/// Whether the instruction returns through an address which is checked against the shadow stack
+pub fn is_shadow_stack_checked(instr: &Instruction) -> bool {
+	matches!(instr.mnemonic(), Mnemonic::Ret | Mnemonic::Retf)
+}
+
 pub fn is_base_pivot_head(instr: &Instruction) -> bool {
 	let reg0 = instr.op0_register();
 	let kind1 = instr.op1_kind();
@@ -206,3 +330,40 @@ pub fn is_base_pivot_head(instr: &Instruction) -> bool {
 		_ => false,
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use iced_x86::{Decoder, DecoderOptions};
+
+	fn sigreturn(bitness: u32, bytes: &[u8]) -> bool {
+		let instructions = Decoder::with_ip(bitness, bytes, 0, DecoderOptions::NONE)
+			.into_iter()
+			.collect::<Vec<_>>();
+		is_sigreturn(&instructions)
+	}
+
+	#[test]
+	fn detects_sigreturn() {
+		// mov eax, 0xf; syscall
+		assert!(sigreturn(64, &[0xb8, 0x0f, 0, 0, 0, 0x0f, 0x05]));
+		// push 0xf; pop rax; syscall
+		assert!(sigreturn(64, &[0x6a, 0x0f, 0x58, 0x0f, 0x05]));
+		// xor eax, eax; mov al, 0xf; syscall
+		assert!(sigreturn(64, &[0x31, 0xc0, 0xb0, 0x0f, 0x0f, 0x05]));
+		// mov eax, 0x77; int 0x80
+		assert!(sigreturn(32, &[0xb8, 0x77, 0, 0, 0, 0xcd, 0x80]));
+	}
+
+	#[test]
+	fn rejects_other_syscalls() {
+		// mov eax, 0xad; int 0x80 - `rt_sigreturn` expects a different frame on i386
+		assert!(!sigreturn(32, &[0xb8, 0xad, 0, 0, 0, 0xcd, 0x80]));
+		// mov eax, 0xf; mov rax, rdi; syscall
+		assert!(!sigreturn(64, &[0xb8, 0x0f, 0, 0, 0, 0x48, 0x89, 0xf8, 0x0f, 0x05]));
+		// mov al, 0xf; syscall - the rest of eax is unknown
+		assert!(!sigreturn(64, &[0xb0, 0x0f, 0x0f, 0x05]));
+		// mov eax, 0xf; ret
+		assert!(!sigreturn(64, &[0xb8, 0x0f, 0, 0, 0, 0xc3]));
+	}
+}
@@ -0,0 +1,63 @@
+use crate::{generic::GenericInstruction, rules::TailKind};
+
+fn is_ret(instr: &GenericInstruction) -> bool {
+	matches!(instr.mnemonic(), "ret" | "retaa" | "retab")
+}
+
+fn is_sys(instr: &GenericInstruction) -> bool {
+	matches!(instr.mnemonic(), "svc" | "eret" | "eretaa" | "eretab")
+}
+
+fn is_jop(instr: &GenericInstruction) -> bool {
+	matches!(
+		instr.mnemonic(),
+		"br" | "braa"
+			| "braaz" | "brab"
+			| "brabz" | "blr"
+			| "blraa" | "blraaz"
+			| "blrab" | "blrabz"
+	)
+}
+
+fn is_conditional_branch(instr: &GenericInstruction) -> bool {
+	instr.mnemonic().starts_with("b.")
+		|| matches!(instr.mnemonic(), "cbz" | "cbnz" | "tbz" | "tbnz")
+}
+
+fn is_store(instr: &GenericInstruction) -> bool { instr.mnemonic().starts_with("st") }
+
+pub fn tail_kind(instr: &GenericInstruction) -> Option<TailKind> {
+	if is_ret(instr) {
+		Some(TailKind::Ret)
+	}
+	else if is_sys(instr) {
+		Some(TailKind::Syscall)
+	}
+	else if is_jop(instr) {
+		Some(TailKind::Jop)
+	}
+	else {
+		None
+	}
+}
+
+pub fn is_rop_gadget_head(instr: &GenericInstruction, noisy: bool) -> bool {
+	if tail_kind(instr).is_some() {
+		return false;
+	}
+	if is_conditional_branch(instr) {
+		return noisy;
+	}
+	!matches!(
+		instr.mnemonic(),
+		"b" | "bl" | "hvc" | "smc" | "brk" | "hlt" | "udf" | "wfi" | "wfe"
+	)
+}
+
+pub fn is_stack_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && matches!(instr.first_operand(), "sp" | "wsp")
+}
+
+pub fn is_base_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && matches!(instr.first_operand(), "x29" | "w29" | "fp")
+}
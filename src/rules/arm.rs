@@ -0,0 +1,133 @@
+use crate::{binary::Arch, generic::GenericInstruction, rules::TailKind};
+
+const CONDITIONS: [&str; 16] = [
+	"eq", "ne", "cs", "hs", "cc", "lo", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le",
+];
+
+/// Mnemonic without any `.w` / `.n` width qualifier
+fn base_mnemonic(instr: &GenericInstruction) -> &str {
+	instr.mnemonic().split('.').next().unwrap_or_default()
+}
+
+/// Registers inside a `{...}` register list operand
+fn register_list(instr: &GenericInstruction) -> Vec<&str> {
+	let operands = instr.operands();
+	match (operands.find('{'), operands.find('}')) {
+		(Some(open), Some(close)) if open < close => operands[open + 1..close]
+			.split(',')
+			.map(str::trim)
+			.collect(),
+		_ => Vec::new(),
+	}
+}
+
+/// Loads multiple registers from the stack, eg. `pop {r4, pc}` or `ldm sp!, {r4, pc}`
+fn is_pop(instr: &GenericInstruction) -> bool {
+	match base_mnemonic(instr) {
+		"pop" => true,
+		"ldm" | "ldmia" | "ldmfd" => instr.first_operand() == "sp!",
+		_ => false,
+	}
+}
+
+fn is_conditional(mnemonic: &str, base: &str) -> bool {
+	mnemonic
+		.strip_prefix(base)
+		.map(|cond| CONDITIONS.contains(&cond))
+		.unwrap_or_default()
+}
+
+fn is_branch(instr: &GenericInstruction) -> bool {
+	let mnemonic = base_mnemonic(instr);
+	matches!(
+		mnemonic,
+		"b" | "bl" | "blx" | "bx" | "cbz" | "cbnz" | "tbb" | "tbh"
+	) || is_conditional_branch(instr)
+}
+
+fn is_conditional_branch(instr: &GenericInstruction) -> bool {
+	let mnemonic = base_mnemonic(instr);
+	["b", "bl", "bx", "blx"]
+		.iter()
+		.any(|base| is_conditional(mnemonic, base))
+		|| matches!(mnemonic, "cbz" | "cbnz")
+}
+
+fn is_store(instr: &GenericInstruction) -> bool {
+	let mnemonic = base_mnemonic(instr);
+	mnemonic.starts_with("st")
+		|| mnemonic.starts_with("vst")
+		|| matches!(mnemonic, "push" | "vpush")
+}
+
+fn writes_pc(instr: &GenericInstruction) -> bool {
+	if is_store(instr) {
+		return false;
+	}
+	instr.first_operand() == "pc" || register_list(instr).contains(&"pc")
+}
+
+fn is_ret(instr: &GenericInstruction) -> bool {
+	match base_mnemonic(instr) {
+		"bx" => instr.operands() == "lr",
+		"mov" => instr.operands() == "pc, lr",
+		"ldr" => instr.operands().starts_with("pc, [sp]"),
+		_ => is_pop(instr) && register_list(instr).contains(&"pc"),
+	}
+}
+
+fn is_sys(instr: &GenericInstruction) -> bool { matches!(base_mnemonic(instr), "svc" | "swi") }
+
+fn is_jop(instr: &GenericInstruction) -> bool {
+	match base_mnemonic(instr) {
+		// Register forms only - immediate forms are direct branches
+		"bx" | "blx" => !instr.operands().starts_with('#'),
+		_ => writes_pc(instr),
+	}
+}
+
+pub fn tail_kind(instr: &GenericInstruction) -> Option<TailKind> {
+	if is_ret(instr) {
+		Some(TailKind::Ret)
+	}
+	else if is_sys(instr) {
+		Some(TailKind::Syscall)
+	}
+	else if is_jop(instr) {
+		Some(TailKind::Jop)
+	}
+	else {
+		None
+	}
+}
+
+pub fn is_rop_gadget_head(instr: &GenericInstruction, noisy: bool) -> bool {
+	if tail_kind(instr).is_some() || writes_pc(instr) {
+		return false;
+	}
+	if is_conditional_branch(instr) {
+		return noisy;
+	}
+	!is_branch(instr)
+		&& !matches!(
+			base_mnemonic(instr),
+			"udf" | "bkpt" | "hvc" | "smc" | "wfi" | "wfe"
+		)
+}
+
+pub fn is_stack_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && (instr.first_operand() == "sp" || register_list(instr).contains(&"sp"))
+}
+
+pub fn is_base_pivot_head(instr: &GenericInstruction) -> bool {
+	// The frame pointer is r11 in ARM code and r7 in Thumb code
+	let frame_pointer: &[&str] = match instr.arch() {
+		Arch::Thumb => &["r7"],
+		_ => &["r11", "fp"],
+	};
+	!is_store(instr)
+		&& (frame_pointer.contains(&instr.first_operand())
+			|| register_list(instr)
+				.iter()
+				.any(|r| frame_pointer.contains(r)))
+}
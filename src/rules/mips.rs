@@ -0,0 +1,86 @@
+use crate::{generic::GenericInstruction, rules::TailKind};
+
+fn is_ret(instr: &GenericInstruction) -> bool {
+	matches!(instr.mnemonic(), "jr" | "jr.hb" | "jrc") && instr.operands() == "$ra"
+}
+
+fn is_sys(instr: &GenericInstruction) -> bool { matches!(instr.mnemonic(), "syscall" | "eret") }
+
+fn is_jop(instr: &GenericInstruction) -> bool {
+	matches!(
+		instr.mnemonic(),
+		"jr" | "jr.hb" | "jrc" | "jalr" | "jalr.hb" | "jalrc"
+	)
+}
+
+fn is_branch(instr: &GenericInstruction) -> bool {
+	matches!(
+		instr.mnemonic(),
+		"j" | "jal" | "jalx" | "b" | "bal" | "bc" | "balc"
+	)
+}
+
+fn is_conditional_branch(instr: &GenericInstruction) -> bool {
+	let mnemonic = instr.mnemonic();
+	mnemonic.starts_with('b') && !is_branch(instr) && mnemonic != "break"
+}
+
+fn is_store(instr: &GenericInstruction) -> bool {
+	let mnemonic = instr.mnemonic();
+	mnemonic.starts_with('s')
+		&& matches!(
+			mnemonic.trim_end_matches(['1', '2']),
+			"sb" | "sh"
+				| "sw" | "sd"
+				| "swl" | "swr"
+				| "sdl" | "sdr"
+				| "sc" | "scd"
+				| "swc" | "sdc"
+		)
+}
+
+/// Jumps and branches are followed by a delay slot which executes before the branch is taken
+pub fn has_delay_slot(instr: &GenericInstruction) -> bool {
+	// Compact (`c` suffixed) branches from MIPS32r6 have no delay slot
+	matches!(instr.mnemonic(), "jr" | "jr.hb" | "jalr" | "jalr.hb")
+}
+
+/// Loads of `$ra` from the stack, eg. `lw $ra, 0x1c($sp)`
+pub fn is_return_address_restore(instr: &GenericInstruction) -> bool {
+	matches!(instr.mnemonic(), "lw" | "ld")
+		&& instr.first_operand() == "$ra"
+		&& instr.operands().ends_with("($sp)")
+}
+
+pub fn tail_kind(instr: &GenericInstruction) -> Option<TailKind> {
+	if is_ret(instr) {
+		Some(TailKind::Ret)
+	}
+	else if is_sys(instr) {
+		Some(TailKind::Syscall)
+	}
+	else if is_jop(instr) {
+		Some(TailKind::Jop)
+	}
+	else {
+		None
+	}
+}
+
+pub fn is_rop_gadget_head(instr: &GenericInstruction, noisy: bool) -> bool {
+	if tail_kind(instr).is_some() || is_branch(instr) {
+		return false;
+	}
+	if is_conditional_branch(instr) {
+		return noisy;
+	}
+	!matches!(instr.mnemonic(), "break" | "sdbbp" | "wait")
+}
+
+pub fn is_stack_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && instr.first_operand() == "$sp"
+}
+
+pub fn is_base_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && matches!(instr.first_operand(), "$fp" | "$s8")
+}
@@ -0,0 +1,55 @@
+use crate::{generic::GenericInstruction, rules::TailKind};
+
+fn is_ret(instr: &GenericInstruction) -> bool { instr.mnemonic() == "blr" }
+
+fn is_sys(instr: &GenericInstruction) -> bool { matches!(instr.mnemonic(), "sc" | "rfi" | "rfid") }
+
+fn is_jop(instr: &GenericInstruction) -> bool {
+	matches!(instr.mnemonic(), "bctr" | "bctrl" | "blrl")
+}
+
+fn is_branch(instr: &GenericInstruction) -> bool {
+	matches!(instr.mnemonic(), "b" | "ba" | "bl" | "bla")
+}
+
+/// Every other branch mnemonic, eg. `beq`, `bdnz`, `bnelr`, is conditional
+fn is_conditional_branch(instr: &GenericInstruction) -> bool {
+	instr.mnemonic().starts_with('b') && !is_branch(instr)
+}
+
+fn is_store(instr: &GenericInstruction) -> bool { instr.mnemonic().starts_with("st") }
+
+pub fn tail_kind(instr: &GenericInstruction) -> Option<TailKind> {
+	if is_ret(instr) {
+		Some(TailKind::Ret)
+	}
+	else if is_sys(instr) {
+		Some(TailKind::Syscall)
+	}
+	else if is_jop(instr) {
+		Some(TailKind::Jop)
+	}
+	else {
+		None
+	}
+}
+
+pub fn is_rop_gadget_head(instr: &GenericInstruction, noisy: bool) -> bool {
+	if tail_kind(instr).is_some() || is_branch(instr) {
+		return false;
+	}
+	if is_conditional_branch(instr) {
+		return noisy;
+	}
+	!matches!(instr.mnemonic(), "trap" | "attn")
+}
+
+/// r1 is the stack pointer
+pub fn is_stack_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && instr.first_operand() == "r1"
+}
+
+/// r31 is used as the frame pointer
+pub fn is_base_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && instr.first_operand() == "r31"
+}
@@ -0,0 +1,89 @@
+use crate::{generic::GenericInstruction, rules::TailKind};
+
+/// Mnemonic without the `c.` prefix of compressed instructions
+fn base_mnemonic(instr: &GenericInstruction) -> &str {
+	let mnemonic = instr.mnemonic();
+	mnemonic.strip_prefix("c.").unwrap_or(mnemonic)
+}
+
+fn is_ret(instr: &GenericInstruction) -> bool {
+	match base_mnemonic(instr) {
+		"ret" => true,
+		"jr" => instr.operands() == "ra",
+		"jalr" => matches!(instr.operands(), "zero, 0(ra)" | "x0, 0(ra)"),
+		_ => false,
+	}
+}
+
+fn is_sys(instr: &GenericInstruction) -> bool {
+	matches!(base_mnemonic(instr), "ecall" | "sret" | "mret" | "uret")
+}
+
+fn is_jop(instr: &GenericInstruction) -> bool { matches!(base_mnemonic(instr), "jr" | "jalr") }
+
+fn is_conditional_branch(instr: &GenericInstruction) -> bool {
+	matches!(
+		base_mnemonic(instr),
+		"beq"
+			| "bne" | "blt"
+			| "bge" | "bltu"
+			| "bgeu" | "beqz"
+			| "bnez" | "blez"
+			| "bgez" | "bltz"
+			| "bgtz" | "bgt"
+			| "ble" | "bgtu"
+			| "bleu"
+	)
+}
+
+fn is_store(instr: &GenericInstruction) -> bool {
+	let mnemonic = base_mnemonic(instr);
+	matches!(
+		mnemonic,
+		"sb" | "sh" | "sw" | "sd" | "sq" | "fsw" | "fsd" | "swsp" | "sdsp"
+	) || mnemonic.starts_with("sc.")
+}
+
+/// Loads of `ra` from the stack, eg. `ld ra, 8(sp)`
+pub fn is_return_address_restore(instr: &GenericInstruction) -> bool {
+	let mnemonic = base_mnemonic(instr);
+	matches!(mnemonic, "ld" | "lw" | "ldsp" | "lwsp")
+		&& instr.first_operand() == "ra"
+		&& instr.operands().ends_with("(sp)")
+}
+
+pub fn tail_kind(instr: &GenericInstruction) -> Option<TailKind> {
+	if is_ret(instr) {
+		Some(TailKind::Ret)
+	}
+	else if is_sys(instr) {
+		Some(TailKind::Syscall)
+	}
+	else if is_jop(instr) {
+		Some(TailKind::Jop)
+	}
+	else {
+		None
+	}
+}
+
+pub fn is_rop_gadget_head(instr: &GenericInstruction, noisy: bool) -> bool {
+	if tail_kind(instr).is_some() {
+		return false;
+	}
+	if is_conditional_branch(instr) {
+		return noisy;
+	}
+	!matches!(
+		base_mnemonic(instr),
+		"j" | "jal" | "ebreak" | "unimp" | "wfi"
+	)
+}
+
+pub fn is_stack_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && instr.first_operand() == "sp"
+}
+
+pub fn is_base_pivot_head(instr: &GenericInstruction) -> bool {
+	!is_store(instr) && matches!(instr.first_operand(), "s0" | "fp")
+}
@@ -0,0 +1,262 @@
+use crate::{
+	binary::Bitness,
+	chain::parse_value,
+	error::{Error, Result},
+	gadgets::Gadget,
+	output::hex,
+};
+use serde::Serialize;
+use std::{
+	fmt::{Display, Formatter},
+	io::{self, Write},
+};
+
+const RESERVED: &str = "reserved";
+
+/// The `rt_sigframe` restored by `rt_sigreturn` on x86-64, starting with the `ucontext` which the
+/// stack pointer points at when the syscall is made
+///
+/// Reserved fields are always zero.
+const FIELDS_64: &[&str] = &[
+	"uc_flags", "uc_link", "ss_sp", "ss_flags", "ss_size", "r8", "r9", "r10", "r11", "r12",
+	"r13", "r14", "r15", "rdi", "rsi", "rbp", "rbx", "rdx", "rax", "rcx", "rsp", "rip",
+	"eflags", "csgsfs", "err", "trapno", "oldmask", "cr2", "fpstate", RESERVED, RESERVED,
+	RESERVED, RESERVED, RESERVED, RESERVED, RESERVED, RESERVED, "sigmask",
+];
+
+/// The `sigcontext` restored by `sigreturn` on i386, which the stack pointer points at when the
+/// syscall is made
+const FIELDS_32: &[&str] = &[
+	"gs", "fs", "es", "ds", "edi", "esi", "ebp", "esp", "ebx", "edx", "ecx", "eax", "trapno",
+	"err", "eip", "cs", "eflags", "esp_at_signal", "ss", "fpstate", "oldmask", "cr2",
+];
+
+/// User mode segment selectors - `csgsfs` holds `cs`, `gs`, `fs` and `ss` from the low bits up
+const DEFAULTS_64: &[(&str, u64)] = &[("csgsfs", 0x002b_0000_0000_0033)];
+/// User mode segment selectors of 32-bit processes on an x86-64 kernel - a native i386 kernel
+/// uses `cs=0x73` and `0x7b` for the others instead
+const DEFAULTS_32: &[(&str, u64)] = &[("cs", 0x23), ("ss", 0x2b), ("ds", 0x2b), ("es", 0x2b)];
+
+/// A signal frame which sets every register at once when `sigreturn` is called with the stack
+/// pointer at the start of the frame
+///
+/// Frames for x86-64 are laid out for `rt_sigreturn` and frames for i386 are laid out for
+/// `sigreturn`, as i386 `rt_sigreturn` expects its frame behind a `siginfo`. Segment registers
+/// are set to their user mode values on an x86-64 kernel, which also runs 32-bit processes.
+#[derive(Debug, Clone)]
+pub struct SigreturnFrame {
+	bitness: Bitness,
+	values: Vec<u64>,
+}
+
+/// A field of a signal frame, at an offset in bytes from the start of the frame
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameField {
+	pub offset: usize,
+	pub name: &'static str,
+	pub value: u64,
+}
+
+impl SigreturnFrame {
+	pub fn new(bitness: Bitness) -> Self {
+		let (fields, defaults) = match bitness {
+			Bitness::Bits64 => (FIELDS_64, DEFAULTS_64),
+			Bitness::Bits32 => (FIELDS_32, DEFAULTS_32),
+		};
+		let mut frame = Self {
+			bitness,
+			values: vec![0; fields.len()],
+		};
+		for (name, value) in defaults {
+			frame = frame.set(name, *value).expect("default fields exist");
+		}
+		frame
+	}
+
+	/// Sets a register or other named field of the frame, such as `rip` or `eflags`
+	pub fn set(mut self, name: &str, value: u64) -> Result<Self> {
+		let index = self
+			.names()
+			.iter()
+			.position(|field| *field != RESERVED && *field == name)
+			.ok_or_else(|| Error::InvalidFrame(format!("no `{}` field", name)))?;
+		self.values[index] = match self.bitness {
+			Bitness::Bits64 => value,
+			Bitness::Bits32 => value & u32::MAX as u64,
+		};
+		Ok(self)
+	}
+
+	/// Parses the fields to set such as `rip=0x401000, rsp=0x7ffe0000, rax=59`
+	pub fn parse(s: &str, bitness: Bitness) -> Result<Self> {
+		let mut frame = Self::new(bitness);
+		for term in s.split([',', ';']).map(str::trim).filter(|t| !t.is_empty()) {
+			let invalid =
+				|| Error::InvalidFrame(format!("expected `name=value` but found `{}`", term));
+			let (name, value) = term.split_once('=').ok_or_else(invalid)?;
+			let value = parse_value(value.trim()).ok_or_else(invalid)?;
+			frame = frame.set(&name.trim().to_lowercase(), value)?;
+		}
+		Ok(frame)
+	}
+
+	pub fn bitness(&self) -> Bitness { self.bitness }
+
+	fn names(&self) -> &'static [&'static str] {
+		match self.bitness {
+			Bitness::Bits64 => FIELDS_64,
+			Bitness::Bits32 => FIELDS_32,
+		}
+	}
+
+	pub fn fields(&self) -> Vec<FrameField> {
+		self.names()
+			.iter()
+			.zip(&self.values)
+			.enumerate()
+			.map(|(index, (name, value))| FrameField {
+				offset: index * self.bitness.word_size(),
+				name,
+				value: *value,
+			})
+			.collect()
+	}
+
+	/// The frame as it should be written to the stack
+	pub fn bytes(&self) -> Vec<u8> {
+		self.values
+			.iter()
+			.flat_map(|value| value.to_le_bytes().into_iter().take(self.bitness.word_size()))
+			.collect()
+	}
+
+	/// Writes the frame as a Python snippet using pwntools' packing helpers, preceded by the
+	/// gadget which invokes `sigreturn` if one was found
+	pub fn write_python(
+		&self,
+		mut w: impl Write,
+		sigreturn: Option<(&Gadget, usize)>,
+	) -> io::Result<()> {
+		let pack = self.bitness.pack_fn();
+		writeln!(w, "from pwn import {}", pack)?;
+		writeln!(w)?;
+		if let Some((gadget, address)) = sigreturn {
+			let mut comment = String::new();
+			gadget.format_instruction(&mut comment);
+			writeln!(w, "SIGRETURN = {:#x}  # {}", address, comment)?;
+			writeln!(w)?;
+		}
+		writeln!(w, "frame = b\"\"")?;
+		for field in self.fields() {
+			writeln!(w, "frame += {}({:#x})  # {}", pack, field.value, field.name)?;
+		}
+		if sigreturn.is_some() {
+			writeln!(w)?;
+			writeln!(w, "payload = {}(SIGRETURN) + frame", pack)?;
+		}
+		Ok(())
+	}
+}
+
+impl Display for SigreturnFrame {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		// Zero padded to a full word, including the `0x` prefix
+		let width = 2 + 2 * self.bitness.word_size();
+		for field in self.fields() {
+			writeln!(
+				f,
+				"{:#06x}  {:#0width$x}  {}",
+				field.offset,
+				field.value,
+				field.name,
+				width = width
+			)?;
+		}
+		Ok(())
+	}
+}
+
+/// Machine-readable description of a frame and the gadget which restores it
+#[derive(Debug, Serialize)]
+pub struct FrameRecord {
+	pub sigreturn: Option<usize>,
+	pub bytes: String,
+	pub fields: Vec<FrameField>,
+}
+
+impl FrameRecord {
+	pub fn new(frame: &SigreturnFrame, sigreturn: Option<usize>) -> Self {
+		Self {
+			sigreturn,
+			bytes: hex(&frame.bytes()),
+			fields: frame.fields(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The field of the frame with the given name
+	fn field(frame: &SigreturnFrame, name: &str) -> FrameField {
+		frame.fields().into_iter().find(|field| field.name == name).unwrap()
+	}
+
+	fn error(s: &str, bitness: Bitness) -> String {
+		SigreturnFrame::parse(s, bitness).unwrap_err().to_string()
+	}
+
+	#[test]
+	fn lays_out_64_bit_frames() {
+		let frame = SigreturnFrame::parse("rip=0x401000, RDI = 0x1234; rax=59", Bitness::Bits64)
+			.unwrap();
+		assert_eq!(field(&frame, "rdi").offset, 0x68);
+		assert_eq!(field(&frame, "rsp").offset, 0xa0);
+		assert_eq!(field(&frame, "rip").offset, 0xa8);
+		assert_eq!(field(&frame, "sigmask").offset, 0x128);
+		assert_eq!(field(&frame, "csgsfs").value, 0x002b_0000_0000_0033);
+		assert_eq!(field(&frame, "rax").value, 59);
+
+		let bytes = frame.bytes();
+		assert_eq!(bytes.len(), 38 * 8);
+		assert_eq!(bytes[0x68..0x70], 0x1234u64.to_le_bytes());
+		assert_eq!(bytes[0xa8..0xb0], 0x401000u64.to_le_bytes());
+	}
+
+	#[test]
+	fn lays_out_32_bit_frames() {
+		let frame =
+			SigreturnFrame::parse("eip=0x8048000, eax=0x10000000b", Bitness::Bits32).unwrap();
+		assert_eq!(field(&frame, "eax").offset, 0x2c);
+		assert_eq!(field(&frame, "eip").offset, 0x38);
+		assert_eq!(field(&frame, "cr2").offset, 0x54);
+		// Values are truncated to the word size
+		assert_eq!(field(&frame, "eax").value, 0xb);
+		for (name, value) in [("cs", 0x23), ("ss", 0x2b), ("ds", 0x2b), ("es", 0x2b)] {
+			assert_eq!(field(&frame, name).value, value);
+		}
+
+		let bytes = frame.bytes();
+		assert_eq!(bytes.len(), 22 * 4);
+		assert_eq!(bytes[0x38..0x3c], 0x8048000u32.to_le_bytes());
+		assert_eq!(bytes[0x3c..0x40], 0x23u32.to_le_bytes());
+	}
+
+	#[test]
+	fn rejects_invalid_fields() {
+		assert_eq!(
+			error("rip", Bitness::Bits64),
+			"invalid sigreturn frame: expected `name=value` but found `rip`"
+		);
+		assert_eq!(
+			error("rip=zz", Bitness::Bits64),
+			"invalid sigreturn frame: expected `name=value` but found `rip=zz`"
+		);
+		assert_eq!(error("rip=1", Bitness::Bits32), "invalid sigreturn frame: no `rip` field");
+		assert_eq!(
+			error("reserved=1", Bitness::Bits64),
+			"invalid sigreturn frame: no `reserved` field"
+		);
+	}
+}
@@ -0,0 +1,201 @@
+use goblin::{
+	elf::{
+		header::ET_REL,
+		section_header::SHN_UNDEF,
+		sym::{Sym, STT_FUNC, STT_GNU_IFUNC},
+		Elf,
+	},
+	pe::PE,
+	strtab::Strtab,
+};
+
+/// A function of a binary, at the address it is loaded at
+#[derive(Debug, Clone)]
+pub struct Symbol {
+	name: String,
+	address: usize,
+	/// Zero if the size is unknown, in which case the function extends to the next symbol or the
+	/// end of its section
+	size: usize,
+	/// The end of the section containing the function, which a function of unknown size doesn't
+	/// extend past
+	section_end: usize,
+}
+
+impl Symbol {
+	pub fn name(&self) -> &str { &self.name }
+
+	pub fn address(&self) -> usize { self.address }
+
+	pub fn size(&self) -> usize { self.size }
+}
+
+/// The functions of a binary sorted by address, so that gadgets can be located within them
+#[derive(Debug, Default)]
+pub struct Symbols {
+	symbols: Vec<Symbol>,
+}
+
+impl Symbols {
+	/// Reads the functions in the `.symtab` and `.dynsym` tables of an ELF binary, moving them
+	/// from `link_base` to `program_base` in the same way as its sections
+	pub(crate) fn elf(e: &Elf, link_base: usize, program_base: usize) -> Self {
+		let relocatable = e.header.e_type == ET_REL;
+		let symbol = |sym: Sym, strtab: &Strtab| {
+			if !matches!(sym.st_type(), STT_FUNC | STT_GNU_IFUNC)
+				|| sym.st_shndx == SHN_UNDEF as usize
+			{
+				return None;
+			}
+			let name = strtab.get_at(sym.st_name).filter(|name| !name.is_empty())?;
+			let section = e.section_headers.get(sym.st_shndx);
+			// Symbols of relocatable objects are relative to their section, which is placed at
+			// its offset within the file
+			let value = match relocatable {
+				true => section?.sh_offset.checked_add(sym.st_value)?,
+				false => sym.st_value,
+			};
+			let section_end = section.and_then(|section| match relocatable {
+				true => section.sh_offset.checked_add(section.sh_size),
+				false => section.sh_addr.checked_add(section.sh_size),
+			});
+			let relocate =
+				|value: u64| (value as usize).checked_sub(link_base)?.checked_add(program_base);
+			let address = relocate(value)?;
+			Some(Symbol {
+				name: name.to_string(),
+				address,
+				size: sym.st_size as usize,
+				// Without its section a function of unknown size only covers its own address
+				section_end: section_end.and_then(relocate).unwrap_or(address.saturating_add(1)),
+			})
+		};
+		let symbols = e
+			.syms
+			.iter()
+			.filter_map(|sym| symbol(sym, &e.strtab))
+			.chain(e.dynsyms.iter().filter_map(|sym| symbol(sym, &e.dynstrtab)))
+			.collect();
+		Self::new(symbols)
+	}
+
+	/// Reads the functions exported by a PE binary, which are placed relative to `program_base`
+	pub(crate) fn pe(p: &PE, program_base: usize) -> Self {
+		let symbols = p
+			.exports
+			.iter()
+			.filter(|export| export.reexport.is_none())
+			.filter_map(|export| {
+				let section = p.sections.iter().find(|section| {
+					let start = section.virtual_address as usize;
+					(start..start.saturating_add(section.virtual_size as usize)).contains(&export.rva)
+				});
+				let address = program_base.checked_add(export.rva)?;
+				let section_end = section
+					.and_then(|section| {
+						let start = section.virtual_address as usize;
+						program_base.checked_add(start.checked_add(section.virtual_size as usize)?)
+					})
+					.unwrap_or(address.saturating_add(1));
+				Some(Symbol {
+					name: export.name?.to_string(),
+					address,
+					size: 0,
+					section_end,
+				})
+			})
+			.collect();
+		Self::new(symbols)
+	}
+
+	/// Sorts symbols by address, keeping a single name for each address - sized symbols are
+	/// preferred, then those with the fewest leading underscores as aliases such as
+	/// `__libc_memcpy` are usually internal
+	fn new(mut symbols: Vec<Symbol>) -> Self {
+		let underscores =
+			|symbol: &Symbol| symbol.name.len() - symbol.name.trim_start_matches('_').len();
+		symbols.sort_by(|a, b| {
+			a.address
+				.cmp(&b.address)
+				.then((a.size == 0).cmp(&(b.size == 0)))
+				.then(underscores(a).cmp(&underscores(b)))
+				.then(a.name.cmp(&b.name))
+		});
+		symbols.dedup_by_key(|symbol| symbol.address);
+		Self { symbols }
+	}
+
+	pub fn is_empty(&self) -> bool { self.symbols.is_empty() }
+
+	pub fn iter(&self) -> impl Iterator<Item = &Symbol> { self.symbols.iter() }
+
+	/// The function containing an address, along with the offset of the address within it
+	pub fn resolve(&self, address: usize) -> Option<(&Symbol, usize)> {
+		let index = self.symbols.partition_point(|symbol| symbol.address <= address);
+		let symbol = self.symbols.get(index.checked_sub(1)?)?;
+		let offset = address - symbol.address;
+		let contained = match symbol.size {
+			0 => address < symbol.section_end,
+			size => offset < size,
+		};
+		contained.then_some((symbol, offset))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn symbol(name: &str, address: usize, size: usize, section_end: usize) -> Symbol {
+		Symbol {
+			name: name.to_string(),
+			address,
+			size,
+			section_end,
+		}
+	}
+
+	fn resolve(symbols: &Symbols, address: usize) -> Option<(&str, usize)> {
+		symbols
+			.resolve(address)
+			.map(|(symbol, offset)| (symbol.name(), offset))
+	}
+
+	#[test]
+	fn resolves_within_sizes() {
+		let symbols = Symbols::new(vec![
+			symbol("g", 0x1100, 0x8, 0x1200),
+			symbol("h", 0x1110, 0x10, 0x1200),
+		]);
+		assert_eq!(resolve(&symbols, 0x10ff), None);
+		assert_eq!(resolve(&symbols, 0x1100), Some(("g", 0)));
+		assert_eq!(resolve(&symbols, 0x1107), Some(("g", 7)));
+		assert_eq!(resolve(&symbols, 0x1108), None);
+		assert_eq!(resolve(&symbols, 0x111f), Some(("h", 0xf)));
+		assert_eq!(resolve(&symbols, 0x1120), None);
+	}
+
+	#[test]
+	fn keeps_unsized_symbols_within_their_section() {
+		// `_init` has no size and is followed by the PLT in another section
+		let symbols = Symbols::new(vec![
+			symbol("_init", 0x1000, 0, 0x101b),
+			symbol("_start", 0x1040, 0x26, 0x1200),
+		]);
+		assert_eq!(resolve(&symbols, 0x1016), Some(("_init", 0x16)));
+		assert_eq!(resolve(&symbols, 0x101b), None);
+		assert_eq!(resolve(&symbols, 0x1032), None);
+		assert_eq!(resolve(&symbols, 0x1040), Some(("_start", 0)));
+	}
+
+	#[test]
+	fn prefers_sized_symbols_at_the_same_address() {
+		let symbols = Symbols::new(vec![
+			symbol("__memcpy", 0x2000, 0, 0x3000),
+			symbol("memcpy_alias", 0x2000, 0, 0x3000),
+			symbol("memcpy", 0x2000, 0x40, 0x3000),
+		]);
+		assert_eq!(resolve(&symbols, 0x2010), Some(("memcpy", 0x10)));
+		assert_eq!(resolve(&symbols, 0x2040), None);
+	}
+}